@@ -1,24 +1,43 @@
+use k8s_openapi::api::admissionregistration::v1::ValidatingWebhookConfiguration;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::api::{Patch, PatchParams};
 use kube::core::{
     admission::{AdmissionRequest, AdmissionResponse, AdmissionReview},
-    DynamicObject,
+    conversion::{ConversionRequest, ConversionResponse, ConversionReview},
+    DynamicObject, Status,
 };
-use kube::Client;
-use serde_json::Value;
+use kube::{Api, Client};
+use serde_json::{json, Value};
 use std::convert::{From, Infallible};
-use tracing::info;
+use tracing::{info, warn};
 use warp::{reply, Filter, Reply};
 
 use crate::controller::Error;
-use crate::crd::Certificate;
+use crate::crd::{v1, Certificate, ClusterIssuer, Issuer};
+
+/// Created by webhook-helper from the manifest, its client config is reused for conversion
+const VALIDATING_WEBHOOK: &str = "certificate-helper";
+const CERTIFICATE_CRD: &str = "certificates.certificate-helper.io";
+const CONVERSION_PATH: &str = "convert";
 
 pub async fn serve(port: u16) -> Result<(), Error> {
     let client = Client::try_default().await?;
 
-    let routes = warp::any()
-        .and(warp::post())
+    if let Err(e) = register_conversion(client.clone()).await {
+        warn!(
+            "Unable to register the Certificate conversion webhook: {}",
+            e
+        );
+    }
+
+    let conversion = warp::path(CONVERSION_PATH)
+        .and(warp::path::end())
         .and(warp::body::json())
-        .and_then(move |body: AdmissionReview<DynamicObject>| handler(client.clone(), body))
-        .with(warp::trace::request());
+        .and_then(convert);
+    let admission = warp::any()
+        .and(warp::body::json())
+        .and_then(move |body: AdmissionReview<DynamicObject>| handler(client.clone(), body));
+    let routes = conversion.or(admission).with(warp::trace::request());
 
     warp::serve(warp::post().and(routes))
         .tls()
@@ -58,16 +77,137 @@ async fn handler(
         None => return Ok(reply::json(&res.into_review())),
     };
 
-    let resource: Certificate = match serde_json::from_value(raw) {
-        Ok(v) => v,
-        Err(_) => {
-            res = res.deny("invalid request format".to_string().to_string());
-            return Ok(reply::json(&res.into_review()));
-        }
-    };
+    if let Err(reason) = validate(
+        &req.kind.kind,
+        &req.kind.version,
+        req.namespace.as_deref(),
+        raw,
+    ) {
+        res = res.deny(reason);
+        return Ok(reply::json(&res.into_review()));
+    }
 
-    info!("Certificate helper validated");
+    info!("{} validated", req.kind.kind);
 
     // Wrap the AdmissionResponse wrapped in an AdmissionReview
     Ok(reply::json(&res.into_review()))
 }
+
+/// Validates the spec of any of the resources the webhook is registered for
+fn validate(kind: &str, version: &str, namespace: Option<&str>, raw: Value) -> Result<(), String> {
+    let invalid = |_| "invalid request format".to_string();
+    match kind {
+        "Issuer" => {
+            let mut issuer = serde_json::from_value::<Issuer>(raw).map_err(invalid)?;
+            issuer.metadata.namespace = namespace.map(String::from);
+            issuer.validate()
+        }
+        "ClusterIssuer" => serde_json::from_value::<ClusterIssuer>(raw)
+            .map_err(invalid)?
+            .spec
+            .validate(),
+        _ if version == "v1" => serde_json::from_value::<v1::Certificate>(raw)
+            .map_err(invalid)?
+            .spec
+            .validate(namespace),
+        _ => serde_json::from_value::<Certificate>(raw)
+            .map_err(invalid)?
+            .spec
+            .validate(namespace.unwrap_or_default()),
+    }
+}
+
+/// Points the conversion webhook of the `Certificate` CRD at this server, using the service
+/// and CA bundle webhook-helper configured for the validating webhook
+async fn register_conversion(client: Client) -> Result<(), Error> {
+    let webhooks: Api<ValidatingWebhookConfiguration> = Api::all(client.clone());
+    let client_config = webhooks
+        .get(VALIDATING_WEBHOOK)
+        .await?
+        .webhooks
+        .and_then(|w| w.into_iter().next())
+        .map(|w| w.client_config)
+        .ok_or(Error::UnableToCreateObject(format!(
+            "ValidatingWebhookConfiguration {} has no webhook",
+            VALIDATING_WEBHOOK
+        )))?;
+    let service = client_config
+        .service
+        .ok_or(Error::UnableToCreateObject(format!(
+            "ValidatingWebhookConfiguration {} has no service",
+            VALIDATING_WEBHOOK
+        )))?;
+
+    let patch = json!({
+        "spec": {
+            "conversion": {
+                "strategy": "Webhook",
+                "webhook": {
+                    "conversionReviewVersions": ["v1"],
+                    "clientConfig": {
+                        "caBundle": client_config.ca_bundle,
+                        "service": {
+                            "name": service.name,
+                            "namespace": service.namespace,
+                            "port": service.port,
+                            "path": format!("/{}", CONVERSION_PATH),
+                        }
+                    }
+                }
+            }
+        }
+    });
+    let crds: Api<CustomResourceDefinition> = Api::all(client);
+    crds.patch(
+        CERTIFICATE_CRD,
+        &PatchParams::default(),
+        &Patch::Merge(&patch),
+    )
+    .await?;
+    info!("Conversion webhook registered for {}", CERTIFICATE_CRD);
+    Ok(())
+}
+
+/// Converts `Certificate` objects between v1 and v2
+async fn convert(review: ConversionReview) -> Result<impl Reply, Infallible> {
+    let request = match ConversionRequest::from_review(review) {
+        Ok(request) => request,
+        Err(err) => {
+            let status = Status::failure(&err.to_string(), "InvalidRequest");
+            return Ok(reply::json(
+                &ConversionResponse::invalid(status).into_review(),
+            ));
+        }
+    };
+
+    let desired = request.desired_api_version.clone();
+    let converted: Result<Vec<Value>, String> = request
+        .objects
+        .iter()
+        .map(|object| convert_certificate(object.clone(), &desired))
+        .collect();
+    let response = ConversionResponse::for_request(request);
+    let response = match converted {
+        Ok(objects) => response.success(objects),
+        Err(reason) => response.failure(Status::failure(&reason, "ConversionFailed")),
+    };
+    Ok(reply::json(&response.into_review()))
+}
+
+fn convert_certificate(object: Value, desired_api_version: &str) -> Result<Value, String> {
+    let invalid = |e: serde_json::Error| e.to_string();
+    if object["apiVersion"] == desired_api_version {
+        return Ok(object);
+    }
+    match desired_api_version.rsplit('/').next() {
+        Some("v1") => {
+            let certificate: Certificate = serde_json::from_value(object).map_err(invalid)?;
+            serde_json::to_value(v1::Certificate::from(certificate)).map_err(invalid)
+        }
+        Some("v2") => {
+            let certificate: v1::Certificate = serde_json::from_value(object).map_err(invalid)?;
+            serde_json::to_value(Certificate::try_from(certificate)?).map_err(invalid)
+        }
+        _ => Err(format!("unsupported apiVersion {}", desired_api_version)),
+    }
+}
@@ -0,0 +1,212 @@
+use futures::StreamExt;
+use k8s_openapi::api::certificates::v1::{
+    CertificateSigningRequest, CertificateSigningRequestCondition,
+};
+use k8s_openapi::api::core::v1::Secret;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
+use k8s_openapi::ByteString;
+use kube::api::PostParams;
+use kube::core::{ObjectMeta, ResourceExt};
+use kube::runtime::controller::Action;
+use kube::runtime::{controller::Controller, watcher, Config};
+use kube::{Api, Client};
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, CertificateSigningRequest as SigningRequest,
+    DistinguishedName, DnType, IsCa, KeyUsagePurpose,
+};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use crate::controller::Error;
+use crate::crd::DEFAULT_DURATION;
+use crate::operator::{apply_usages, load_ca, random_serial, HELPER_CA};
+use crate::RunArgs;
+
+/// Lifetime of the CA generated when no CA Secret exists yet
+const CA_DURATION: Duration = Duration::from_secs(10 * 365 * 24 * 60 * 60);
+
+struct SignerContext {
+    kube: Client,
+    args: RunArgs,
+}
+
+fn is_approved(csr: &CertificateSigningRequest) -> bool {
+    let conditions = csr
+        .status
+        .as_ref()
+        .and_then(|s| s.conditions.clone())
+        .unwrap_or_default();
+
+    let approved = conditions
+        .iter()
+        .any(|c| c.type_ == "Approved" && c.status == "True");
+    let rejected = conditions
+        .iter()
+        .any(|c| (c.type_ == "Denied" || c.type_ == "Failed") && c.status == "True");
+    approved && !rejected
+}
+
+fn is_issued(csr: &CertificateSigningRequest) -> bool {
+    csr.status.as_ref().is_some_and(|s| s.certificate.is_some())
+}
+
+/// Creates a self-signed CA Secret the first time the signer starts
+async fn ensure_ca(client: Client, args: &RunArgs) -> Result<(), Error> {
+    let api: Api<Secret> = Api::namespaced(client, &args.ca_secret_namespace);
+    if api.get_opt(&args.ca_secret_name).await?.is_some() {
+        return Ok(());
+    }
+
+    let mut params = CertificateParams::default();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    params.distinguished_name = DistinguishedName::new();
+    params
+        .distinguished_name
+        .push(DnType::CommonName, "certificate-helper CA");
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params.not_before = OffsetDateTime::now_utc();
+    params.not_after = params.not_before + CA_DURATION;
+    let ca = Certificate::from_params(params)?;
+
+    let mut data: BTreeMap<String, ByteString> = BTreeMap::new();
+    data.insert(
+        "tls.key".into(),
+        ByteString(ca.serialize_private_key_pem().into_bytes()),
+    );
+    data.insert(
+        "tls.crt".into(),
+        ByteString(ca.serialize_pem()?.into_bytes()),
+    );
+
+    let secret = Secret {
+        type_: Some("kubernetes.io/tls".into()),
+        metadata: ObjectMeta {
+            name: Some(args.ca_secret_name.clone()),
+            namespace: Some(args.ca_secret_namespace.clone()),
+            ..Default::default()
+        },
+        data: Some(data),
+        ..Default::default()
+    };
+    api.create(&PostParams::default(), &secret).await?;
+    info!(
+        "Created CA Secret {}/{}",
+        args.ca_secret_namespace, args.ca_secret_name
+    );
+    Ok(())
+}
+
+/// Signs the request with the CA, applying the lifetime and usages from the CSR spec
+fn sign(csr: &CertificateSigningRequest, ca: &Certificate) -> Result<String, Error> {
+    let pem = String::from_utf8(csr.spec.request.0.clone())
+        .map_err(|e| Error::UnableToParseCertificate(e.to_string()))?;
+    let mut request = SigningRequest::from_pem(&pem)?;
+
+    let duration = csr
+        .spec
+        .expiration_seconds
+        .map_or(DEFAULT_DURATION, |s| Duration::from_secs(s as u64));
+
+    request.params.not_before = OffsetDateTime::now_utc();
+    request.params.not_after = request.params.not_before + duration;
+    request.params.serial_number = Some(random_serial());
+    request.params.use_authority_key_identifier_extension = true;
+    apply_usages(
+        &mut request.params,
+        &csr.spec.usages.clone().unwrap_or_default(),
+    );
+
+    Ok(request.serialize_pem_with_signer(ca)?)
+}
+
+async fn reconcile(
+    csr: Arc<CertificateSigningRequest>,
+    ctx: Arc<SignerContext>,
+) -> Result<Action, Error> {
+    if csr.spec.signer_name != HELPER_CA || is_issued(&csr) || !is_approved(&csr) {
+        return Ok(Action::await_change());
+    }
+
+    let api: Api<CertificateSigningRequest> = Api::all(ctx.kube.clone());
+    let mut updated = (*csr).clone();
+    let mut status = updated.status.clone().unwrap_or_default();
+
+    let ca = load_ca(
+        ctx.kube.clone(),
+        &ctx.args.ca_secret_name,
+        &ctx.args.ca_secret_namespace,
+    )
+    .await?;
+    match sign(&csr, &ca) {
+        Ok(certificate) => {
+            status.certificate = Some(ByteString(certificate.into_bytes()));
+            info!("Signed CSR {}", csr.name_any());
+        }
+        Err(e) => {
+            warn!("Unable to sign CSR {}: {}", csr.name_any(), e);
+            let mut conditions = status.conditions.clone().unwrap_or_default();
+            conditions.push(CertificateSigningRequestCondition {
+                type_: "Failed".into(),
+                status: "True".into(),
+                reason: Some("SigningFailed".into()),
+                message: Some(e.to_string()),
+                last_transition_time: Some(Time(chrono::Utc::now())),
+                last_update_time: Some(Time(chrono::Utc::now())),
+            });
+            status.conditions = Some(conditions);
+        }
+    };
+
+    updated.status = Some(status);
+    let data: Vec<u8> = serde_json::to_vec(&updated)?;
+    api.replace_status(&csr.name_any(), &PostParams::default(), data)
+        .await?;
+
+    Ok(Action::await_change())
+}
+
+fn error_policy(
+    obj: Arc<CertificateSigningRequest>,
+    error: &Error,
+    _ctx: Arc<SignerContext>,
+) -> Action {
+    warn!("CSR {} received error {:?}", obj.name_any(), error);
+    Action::requeue(Duration::from_secs(30))
+}
+
+/// Signs approved `CertificateSigningRequest`s addressed to `certificate-helper.io/ca` with
+/// the CA stored in the configured Secret
+pub async fn run(args: RunArgs) -> Result<(), Error> {
+    let client = Client::try_default().await?;
+    ensure_ca(client.clone(), &args).await?;
+
+    let api: Api<CertificateSigningRequest> = Api::all(client.clone());
+    let context = Arc::new(SignerContext {
+        kube: client.clone(),
+        args,
+    });
+
+    let selector = format!("spec.signerName={}", HELPER_CA);
+    Controller::new(api, watcher::Config::default().fields(&selector))
+        .with_config(Config::default().concurrency(2))
+        .shutdown_on_signal()
+        .run(reconcile, error_policy, context)
+        .for_each(|res| async move {
+            match res {
+                Ok((o, _)) => info!("signer reconcile complete for {}", o.name),
+                Err(e) => warn!("signer reconcile failed: {:?}", e),
+            }
+        })
+        .await;
+
+    println!("Signer terminated");
+
+    Ok(())
+}
@@ -1,3 +1,5 @@
+use chrono::{DateTime, Utc};
+use k8s_openapi::api::core::v1::Secret;
 use kube::api::{Patch, PatchParams};
 use kube::core::ResourceExt;
 use kube::runtime::controller::Action;
@@ -9,16 +11,23 @@ use thiserror::Error;
 use tracing::{error, info, warn};
 
 use futures::StreamExt;
+use kube::runtime::reflector::{ObjectRef, Store};
 use kube::runtime::{controller::Controller, watcher, Config};
 
-use crate::crd::{Certificate, Stage};
-use crate::operator::{determine_stage, CertificateStage, Operation};
+use crate::crd::{Certificate, ClusterIssuer, Issuer, IssuerKind, Stage, CONDITION_ISSUING};
+use crate::operator::{determine_stage, CertificateStage, Operation, CERTIFICATE_LABEL};
+use crate::RunArgs;
+
+/// Longest time between reconciles of an issued certificate, bounds how long a rotated CA
+/// takes to reach `ca.crt`
+const CA_SYNC_INTERVAL: Duration = Duration::from_secs(3600);
+/// Minimum time between attempts to issue a certificate whose last attempt failed
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("Failed to create Certificate: {0}")]
     WebhookHelperCreationFailed(#[from] kube::Error),
-    #[allow(dead_code)]
     #[error("UnableToCreateObject: {0}")]
     UnableToCreateObject(String),
     #[error("UnableToSerializeObject: {0}")]
@@ -27,12 +36,29 @@ pub enum Error {
     UnableToApproveCertificate(#[from] http::Error),
     #[error("UnableToGenerateCertificate: {0}")]
     UnableToGenerateCertificate(#[from] rcgen::Error),
+    #[error("UnableToGenerateKey: {0}")]
+    UnableToGenerateKey(String),
+    #[error("UnableToCreateKeystore: {0}")]
+    UnableToCreateKeystore(String),
     #[error("UnknownOperation: {0}")]
     UnknownOperation(String),
+    #[error("UnableToParseCertificate: {0}")]
+    UnableToParseCertificate(String),
+    #[error("InvalidSpec: {0}")]
+    InvalidSpec(String),
+    #[error("IssuerNotReady: {0}")]
+    IssuerNotReady(String),
+    #[error("AcmeRequestFailed: {0}")]
+    AcmeRequestFailed(#[from] instant_acme::Error),
+    #[error("AcmeOrderFailed: {0}")]
+    AcmeOrderFailed(String),
+    #[error("CertificateRequestFailed: {0}")]
+    CertificateRequestFailed(String),
 }
 
 struct CustomClients {
     kube: Client,
+    args: RunArgs,
 }
 
 enum CustomAction {
@@ -89,7 +115,8 @@ fn determine_action<T: kube::core::Resource>(echo: &T) -> CustomAction {
 async fn reconcile(g: Arc<Certificate>, ctx: Arc<CustomClients>) -> Result<Action, Error> {
     // .. use api here to reconcile a child ConfigMap with ownerreferences
     // see configmapgen_controller example for full info
-    let cert_api: Api<Certificate> = Api::all(ctx.kube.clone());
+    let cert_api: Api<Certificate> =
+        Api::namespaced(ctx.kube.clone(), &g.namespace().unwrap_or_default());
     let name = g.name_any();
 
     let d = match cert_api.get(name.as_str()).await {
@@ -112,9 +139,18 @@ async fn reconcile(g: Arc<Certificate>, ctx: Arc<CustomClients>) -> Result<Actio
     if let Some(z) = d {
         match determine_action(&z) {
             CustomAction::Create => {
+                // The finalizer is only added once issuance succeeds, so failed first
+                // attempts come back through here on their own status writes
+                if let Some(wait) = retry_backoff(&z) {
+                    return Ok(Action::requeue(wait));
+                }
                 info!("Creating certificate {}", z.name_any());
-                let mut cert_stage =
-                    CertificateStage::new(ctx.kube.clone(), Operation::Create, z.clone());
+                let mut cert_stage = CertificateStage::new(
+                    ctx.kube.clone(),
+                    ctx.args.clone(),
+                    Operation::Create,
+                    z.clone(),
+                );
                 match cert_stage.run().await {
                     Ok(_) => {
                         let finalizer: Value = json!({
@@ -136,8 +172,12 @@ async fn reconcile(g: Arc<Certificate>, ctx: Arc<CustomClients>) -> Result<Actio
 
                 if let Some(status) = z.status.clone() {
                     if status.certificate.is_some() {
-                        let mut cert_stage =
-                            CertificateStage::new(ctx.kube.clone(), Operation::Delete, z.clone());
+                        let mut cert_stage = CertificateStage::new(
+                            ctx.kube.clone(),
+                            ctx.args.clone(),
+                            Operation::Delete,
+                            z.clone(),
+                        );
                         cert_stage.run().await?;
                     }
                 };
@@ -161,11 +201,68 @@ async fn reconcile(g: Arc<Certificate>, ctx: Arc<CustomClients>) -> Result<Actio
                 }
                 Stage::CertificateCreated(s) => {
                     info!("Certificate created {}: {}", z.name_any(), s);
-                    return Ok(Action::await_change());
+                    let cert_stage = CertificateStage::new(
+                        ctx.kube.clone(),
+                        ctx.args.clone(),
+                        Operation::Get,
+                        z.clone(),
+                    );
+                    let reissue = match cert_stage.secret_drift().await? {
+                        Some(reason) => Some(reason),
+                        None if cert_stage.spec_changed().await? => Some("spec changed".into()),
+                        None => None,
+                    };
+                    if let Some(reason) = reissue {
+                        info!("Reissuing {}: {}", z.name_any(), reason);
+                        let mut cert_stage = CertificateStage::new(
+                            ctx.kube.clone(),
+                            ctx.args.clone(),
+                            Operation::Update,
+                            z.clone(),
+                        );
+                        cert_stage.run().await?;
+                        return Ok(Action::requeue(Duration::from_secs(5)));
+                    }
+                    match cert_stage
+                        .time_until_renewal(ctx.args.renewal_fraction)
+                        .await?
+                    {
+                        Some(remaining) => {
+                            cert_stage.apply_secret_template().await?;
+                            cert_stage.sync_ca().await?;
+                            info!(
+                                "Certificate {} due for renewal in {:?}",
+                                z.name_any(),
+                                remaining
+                            );
+                            return Ok(Action::requeue(remaining.min(CA_SYNC_INTERVAL)));
+                        }
+                        None => {
+                            info!("Renewing certificate {}", z.name_any());
+                            let mut cert_stage = CertificateStage::new(
+                                ctx.kube.clone(),
+                                ctx.args.clone(),
+                                Operation::Update,
+                                z.clone(),
+                            );
+                            cert_stage.run().await?;
+                        }
+                    };
                 }
-                Stage::CreationFailed(_) => {
-                    info!("Creation failed for {}", z.name_any());
-                    return Ok(Action::await_change());
+                Stage::CreationFailed(reason) => {
+                    // Every status write wakes the controller again, so the wait between
+                    // attempts is enforced here rather than left to error_policy
+                    if let Some(wait) = retry_backoff(&z) {
+                        return Ok(Action::requeue(wait));
+                    }
+                    info!("Retrying failed issuance of {}: {}", z.name_any(), reason);
+                    let mut cert_stage = CertificateStage::new(
+                        ctx.kube.clone(),
+                        ctx.args.clone(),
+                        Operation::Update,
+                        z.clone(),
+                    );
+                    cert_stage.run().await?;
                 }
                 Stage::Deleting => {}
             },
@@ -177,28 +274,119 @@ async fn reconcile(g: Arc<Certificate>, ctx: Arc<CustomClients>) -> Result<Actio
 
     Ok(Action::requeue(Duration::from_secs(5)))
 }
+
+/// Time left before a failed certificate is retried, counted from the end of the last
+/// attempt when `Issuing` turned `False`. A spec change is retried straight away.
+fn retry_backoff(certificate: &Certificate) -> Option<Duration> {
+    let issuing = certificate
+        .status
+        .as_ref()?
+        .conditions
+        .iter()
+        .flatten()
+        .find(|c| c.type__ == CONDITION_ISSUING && c.status == "False")?;
+    if issuing.observed_generation != certificate.metadata.generation {
+        return None;
+    }
+    let attempted = DateTime::parse_from_rfc3339(&issuing.last_transition_time).ok()?;
+    let elapsed = (Utc::now() - attempted.with_timezone(&Utc))
+        .to_std()
+        .unwrap_or_default();
+    RETRY_INTERVAL
+        .checked_sub(elapsed)
+        .filter(|wait| !wait.is_zero())
+}
+
 /// an error handler that will be called when the reconciler fails with access to both the
 /// object that caused the failure and the actual error
 fn error_policy(obj: Arc<Certificate>, error: &Error, _ctx: Arc<CustomClients>) -> Action {
     let def_str = serde_json::to_string(&obj.spec).unwrap();
     println!("{} received error {:?}", def_str, error);
-    Action::requeue(Duration::from_secs(60))
+    Action::requeue(RETRY_INTERVAL)
+}
+
+/// Certificates referencing the given issuer, so they are retried once it becomes Ready
+fn certificates_for(
+    store: &Store<Certificate>,
+    kind: IssuerKind,
+    name: &str,
+    namespace: Option<&str>,
+) -> Vec<ObjectRef<Certificate>> {
+    store
+        .state()
+        .iter()
+        .filter(|c| {
+            c.spec.issuer_ref.as_ref().is_some_and(|r| {
+                r.kind == kind
+                    && r.name.as_deref() == Some(name)
+                    && (namespace.is_none()
+                        || namespace == r.namespace.clone().or(c.namespace()).as_deref())
+            })
+        })
+        .map(|c| ObjectRef::from_obj(c.as_ref()))
+        .collect()
 }
 
-pub async fn run() -> Result<(), Error> {
+/// The `Certificate` a managed Secret belongs to, so edits and deletions are repaired. The
+/// label holds the UID, the name comes from the owner reference with that UID.
+fn certificate_for_secret(secret: Secret) -> Option<ObjectRef<Certificate>> {
+    let uid = secret.labels().get(CERTIFICATE_LABEL)?;
+    let owner = secret
+        .owner_references()
+        .iter()
+        .find(|o| o.kind == "Certificate" && o.uid == *uid)?;
+    let namespace = secret.namespace()?;
+    Some(ObjectRef::new(&owner.name).within(&namespace))
+}
+
+pub async fn run(args: RunArgs) -> Result<(), Error> {
     let client = Client::try_default().await?;
     let api: Api<Certificate> = Api::all(client.clone());
 
     let clients = CustomClients {
         kube: client.clone(),
+        args,
     };
 
     let context = Arc::new(clients); // bad empty context - put client in here
 
     let config = Config::default().concurrency(2);
 
-    Controller::new(api.clone(), watcher::Config::default())
+    let controller = Controller::new(api.clone(), watcher::Config::default());
+    let issuer_store = controller.store();
+    let cluster_issuer_store = controller.store();
+
+    controller
         .owns(api, watcher::Config::default())
+        .watches(
+            Api::<Issuer>::all(client.clone()),
+            watcher::Config::default(),
+            move |issuer| {
+                certificates_for(
+                    &issuer_store,
+                    IssuerKind::Issuer,
+                    &issuer.name_any(),
+                    issuer.namespace().as_deref(),
+                )
+            },
+        )
+        .watches(
+            Api::<ClusterIssuer>::all(client.clone()),
+            watcher::Config::default(),
+            move |issuer| {
+                certificates_for(
+                    &cluster_issuer_store,
+                    IssuerKind::ClusterIssuer,
+                    &issuer.name_any(),
+                    None,
+                )
+            },
+        )
+        .watches(
+            Api::<Secret>::all(client.clone()),
+            watcher::Config::default().labels(CERTIFICATE_LABEL),
+            certificate_for_secret,
+        )
         .with_config(config.clone())
         .shutdown_on_signal()
         .run(reconcile, error_policy, context.clone())
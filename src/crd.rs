@@ -1,6 +1,52 @@
-use kube::CustomResource;
+use chrono::offset::Utc;
+use chrono::SecondsFormat;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+use kube::core::crd::{merge_crds, MergeError};
+use kube::{CustomResource, CustomResourceExt};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+/// Lifetime requested when a `Certificate` does not set `duration`
+pub const DEFAULT_DURATION: Duration = Duration::from_secs(86400);
+/// Shortest lifetime accepted by the Kubernetes signers
+pub const MINIMUM_DURATION: Duration = Duration::from_secs(600);
+/// Longest lifetime that fits the 32 bit `expirationSeconds` of a CSR
+pub const MAXIMUM_DURATION: Duration = Duration::from_secs(i32::MAX as u64);
+/// Signer the kube-controller-manager never signs for, requests to it would never complete
+pub const LEGACY_UNKNOWN_SIGNER: &str = "kubernetes.io/legacy-unknown";
+/// Prefixes of the users and groups Kubernetes and kubeadm reserve for system components
+/// and cluster admins, e.g. `system:masters` and `kubeadm:cluster-admins`
+const RESERVED_IDENTITY_PREFIXES: [&str; 2] = ["system:", "kubeadm:"];
+/// Usages accepted by the `CertificateSigningRequest` API
+pub const USAGES: [&str; 23] = [
+    "signing",
+    "digital signature",
+    "content commitment",
+    "key encipherment",
+    "key agreement",
+    "data encipherment",
+    "cert sign",
+    "crl sign",
+    "encipher only",
+    "decipher only",
+    "any",
+    "server auth",
+    "client auth",
+    "code signing",
+    "email protection",
+    "s/mime",
+    "ipsec end system",
+    "ipsec tunnel",
+    "ipsec user",
+    "timestamping",
+    "ocsp signing",
+    "microsoft sgc",
+    "netscape sgc",
+];
 
 #[derive(Serialize, Deserialize, Clone)]
 pub enum Stage {
@@ -25,22 +71,82 @@ impl std::fmt::Display for Stage {
 impl Stage {
     pub fn message(&self) -> String {
         match self {
-            Stage::CertificateCreated(c) => format!("Certificate {} Created", c),
-            Stage::CreationFailed(r) => format!("Webhook-helper failed to created webhook: {}", r),
+            Stage::CertificateCreated(c) => format!("Certificate written to Secret {}", c),
+            Stage::CreationFailed(r) => format!("Unable to issue certificate: {}", r),
             Stage::Deleting => "Deleting resource".into(),
-            Stage::Creating => "Creating resource".into(),
+            Stage::Creating => "Issuing certificate".into(),
         }
     }
 }
 
+/// The current certificate is valid and stored in its Secret
+pub const CONDITION_READY: &str = "Ready";
+/// A certificate is being issued
+pub const CONDITION_ISSUING: &str = "Issuing";
+/// The last attempt to issue a certificate failed
+pub const CONDITION_FAILED: &str = "Failed";
+
+/// Status condition following the Kubernetes conventions, one entry per `type`
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
 pub struct CertificateCondition {
     #[serde(rename = "type")]
     pub type__: String,
-    pub message: String,
+    /// `True`, `False` or `Unknown`
     pub status: String,
+    /// CamelCase reason for the last transition, e.g. `Issued`
+    #[serde(default)]
+    pub reason: String,
+    pub message: String,
+    /// RFC 3339 time the status last changed
     #[serde(rename = "lastTransitionTime")]
     pub last_transition_time: String,
+    /// `metadata.generation` the condition was set for
+    #[serde(rename = "observedGeneration")]
+    pub observed_generation: Option<i64>,
+}
+
+impl CertificateCondition {
+    pub fn new(
+        type_: &str,
+        status: bool,
+        reason: &str,
+        message: String,
+        observed_generation: Option<i64>,
+    ) -> CertificateCondition {
+        CertificateCondition {
+            type__: type_.into(),
+            status: if status { "True" } else { "False" }.into(),
+            reason: reason.into(),
+            message,
+            last_transition_time: Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+            observed_generation,
+        }
+    }
+}
+
+/// Whether the condition of the given type is `True`
+pub fn is_condition_true(conditions: &Option<Vec<CertificateCondition>>, type_: &str) -> bool {
+    conditions
+        .iter()
+        .flatten()
+        .any(|c| c.type__ == type_ && c.status == "True")
+}
+
+/// Replaces the condition of the same type, keeping its `lastTransitionTime` when the
+/// status did not change
+pub fn set_condition(
+    conditions: &mut Vec<CertificateCondition>,
+    mut condition: CertificateCondition,
+) {
+    match conditions.iter_mut().find(|c| c.type__ == condition.type__) {
+        Some(existing) => {
+            if existing.status == condition.status {
+                condition.last_transition_time = existing.last_transition_time.clone();
+            }
+            *existing = condition;
+        }
+        None => conditions.push(condition),
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
@@ -49,14 +155,731 @@ pub struct CertificateStatus {
     pub service: Option<String>,
     pub alt_names: Option<Vec<String>>,
     pub conditions: Option<Vec<CertificateCondition>>,
+    /// Algorithm of the current key, e.g. `RSA-2048` or `ECDSA-P256`
+    #[serde(rename = "keyAlgorithm")]
+    pub key_algorithm: Option<String>,
+    /// Validity of the current certificate, RFC 3339
+    #[serde(rename = "notBefore")]
+    pub not_before: Option<String>,
+    #[serde(rename = "notAfter")]
+    pub not_after: Option<String>,
+    /// When the certificate will be renewed, RFC 3339
+    #[serde(rename = "renewalTime")]
+    pub renewal_time: Option<String>,
+    #[serde(rename = "serialNumber")]
+    pub serial_number: Option<String>,
+    #[serde(rename = "fingerprintSHA256")]
+    pub fingerprint_sha256: Option<String>,
+    /// Distinguished name of the CA that signed the certificate
+    pub issuer: Option<String>,
+    /// `metadata.generation` the current certificate was issued for
+    #[serde(rename = "observedGeneration")]
+    pub observed_generation: Option<i64>,
+}
+
+/// Backend used to sign a `Certificate`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub enum IssuerKind {
+    /// Kubernetes `CertificateSigningRequest` API using `signerName`
+    #[serde(rename = "KubernetesCSR")]
+    KubernetesCsr,
+    /// Certificate signed by its own key
+    SelfSigned,
+    /// Signed locally with the CA stored in the Secret `name`
+    #[serde(rename = "CA")]
+    Ca,
+    /// Signed as described by the namespaced `Issuer` `name`
+    Issuer,
+    /// Signed as described by the `ClusterIssuer` `name`
+    ClusterIssuer,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct IssuerRef {
+    pub kind: IssuerKind,
+    pub name: Option<String>,
+    /// Namespace of the CA Secret or `Issuer`, must be the certificate's namespace if set.
+    /// Use a `ClusterIssuer` to sign with a CA from another namespace.
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, JsonSchema, Default)]
+pub enum KeyAlgorithm {
+    /// RSA key of `size` bits: 2048 (default), 3072 or 4096
+    #[serde(rename = "RSA")]
+    Rsa,
+    /// ECDSA key on the P-256 (default) or P-384 curve, selected by `size` 256 or 384
+    #[default]
+    #[serde(rename = "ECDSA")]
+    Ecdsa,
+    Ed25519,
+}
+
+/// Extra encodings of the certificate and key written to the Secret
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, JsonSchema)]
+pub enum OutputFormat {
+    /// `tls-combined.pem`: certificate chain followed by the private key
+    #[serde(rename = "CombinedPEM")]
+    CombinedPem,
+    /// `key.der`: private key as DER encoded PKCS#8
+    #[serde(rename = "DER")]
+    Der,
 }
 
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, JsonSchema, Default)]
+pub enum RotationPolicy {
+    /// Generate a new key every time the certificate is issued
+    #[default]
+    Always,
+    /// Keep the key already stored in the Secret when renewing
+    Never,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
+pub struct PrivateKey {
+    /// Defaults to `ECDSA`
+    pub algorithm: Option<KeyAlgorithm>,
+    pub size: Option<u32>,
+    /// Defaults to `Always`
+    #[serde(rename = "rotationPolicy")]
+    pub rotation_policy: Option<RotationPolicy>,
+}
+
+impl PrivateKey {
+    pub fn rotation_policy(&self) -> RotationPolicy {
+        self.rotation_policy.unwrap_or_default()
+    }
+
+    pub fn algorithm(&self) -> KeyAlgorithm {
+        self.algorithm.unwrap_or_default()
+    }
+
+    /// Key size in bits, `None` for Ed25519 which has a fixed size
+    pub fn size(&self) -> Option<u32> {
+        match self.algorithm() {
+            KeyAlgorithm::Rsa => Some(self.size.unwrap_or(2048)),
+            KeyAlgorithm::Ecdsa => Some(self.size.unwrap_or(256)),
+            KeyAlgorithm::Ed25519 => None,
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = match self.algorithm() {
+            KeyAlgorithm::Rsa => matches!(self.size(), Some(2048 | 3072 | 4096)),
+            KeyAlgorithm::Ecdsa => matches!(self.size(), Some(256 | 384)),
+            KeyAlgorithm::Ed25519 => self.size.is_none(),
+        };
+        if !valid {
+            return Err(format!(
+                "privateKey.size {} is not supported for {:?}",
+                self.size.unwrap_or_default(),
+                self.algorithm()
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, JsonSchema, Default)]
+pub enum CertificateMode {
+    /// Serving certificate for `service`
+    #[default]
+    Server,
+    /// Client certificate identified by `subject.commonName` and `subject.organizations`,
+    /// for authenticating over mTLS
+    Client,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
+pub struct SecretTemplate {
+    pub labels: Option<BTreeMap<String, String>>,
+    pub annotations: Option<BTreeMap<String, String>>,
+}
+
+/// Key of a Secret in the certificate's namespace
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct SecretKeySelector {
+    pub name: String,
+    pub key: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct KeystoreConfig {
+    /// Password protecting the keystore and truststore
+    #[serde(rename = "passwordSecretRef")]
+    pub password_secret_ref: SecretKeySelector,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
+pub struct Keystores {
+    /// Writes `keystore.p12` and `truststore.p12`
+    pub pkcs12: Option<KeystoreConfig>,
+    /// Writes `keystore.jks` and `truststore.jks`
+    pub jks: Option<KeystoreConfig>,
+}
+
+/// Subject distinguished name. Each list may hold a single value, rcgen can only encode one
+/// value per attribute type.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
+pub struct Subject {
+    /// Defaults to the service name
+    #[serde(rename = "commonName")]
+    pub common_name: Option<String>,
+    pub organizations: Option<Vec<String>>,
+    #[serde(rename = "organizationalUnits")]
+    pub organizational_units: Option<Vec<String>>,
+    pub countries: Option<Vec<String>>,
+    pub localities: Option<Vec<String>>,
+    pub provinces: Option<Vec<String>>,
+    #[serde(rename = "serialNumber")]
+    pub serial_number: Option<String>,
+}
+
+impl Subject {
+    pub fn validate(&self) -> Result<(), String> {
+        let lists = [
+            ("organizations", &self.organizations),
+            ("organizationalUnits", &self.organizational_units),
+            ("countries", &self.countries),
+            ("localities", &self.localities),
+            ("provinces", &self.provinces),
+        ];
+        for (field, values) in lists {
+            if values.as_ref().is_some_and(|v| v.len() > 1) {
+                return Err(format!("subject.{} may only hold a single value", field));
+            }
+        }
+        Ok(())
+    }
+
+    /// The API server authenticates `kubernetes.io/kube-apiserver-client` certificates as
+    /// the user in the common name and the groups in the organizations, so those have to
+    /// be prefixed with the certificate's namespace, e.g. `my-namespace:batch-job`
+    pub fn check_apiserver_identity(&self, namespace: &str) -> Result<(), String> {
+        let common_name = self.common_name.iter().map(|cn| ("subject.commonName", cn));
+        let organizations = self
+            .organizations
+            .iter()
+            .flatten()
+            .map(|o| ("subject.organizations", o));
+        let prefix = format!("{}:", namespace);
+        for (field, identity) in common_name.chain(organizations) {
+            if RESERVED_IDENTITY_PREFIXES
+                .iter()
+                .any(|reserved| identity.starts_with(reserved))
+            {
+                return Err(format!("{} {} is a reserved identity", field, identity));
+            }
+            if !identity.starts_with(&prefix) {
+                return Err(format!(
+                    "{} {} must start with {} for signers trusted by the API server",
+                    field, identity, prefix
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A certificate written to a Secret in the namespace of the `Certificate`
 #[derive(CustomResource, Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
-#[kube(group = "certificate-helper.io", version = "v1", kind = "Certificate")]
+#[kube(
+    group = "certificate-helper.io",
+    version = "v2",
+    kind = "Certificate",
+    namespaced
+)]
 #[kube(singular = "certificate", plural = "certificates")]
+#[kube(shortname = "cert", shortname = "crt")]
+#[kube(
+    printcolumn = r#"{"name":"Ready","type":"string","jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+    printcolumn = r#"{"name":"Secret","type":"string","jsonPath":".status.certificate"}"#,
+    printcolumn = r#"{"name":"NotAfter","type":"date","jsonPath":".status.notAfter"}"#,
+    printcolumn = r#"{"name":"Signer","type":"string","jsonPath":".spec.signerName"}"#,
+    printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#
+)]
 #[kube(status = "CertificateStatus")]
 pub struct CertificateSpec {
-    pub namespace: String,
-    pub service: String,
+    /// Service the certificate is for, required unless `mode` is `Client`
+    pub service: Option<String>,
+    /// Whether the certificate identifies a server or a client, defaults to `Server`
+    pub mode: Option<CertificateMode>,
     pub alt_names: Option<Vec<String>>,
+    /// Requested lifetime of the certificate, e.g. `24h` or `30d`
+    pub duration: Option<String>,
+    /// How long before expiry the certificate is renewed, e.g. `8h`
+    #[serde(rename = "renewBefore")]
+    pub renew_before: Option<String>,
+    /// Signer the CSR is addressed to, defaults to `kubernetes.io/kubelet-serving`
+    #[serde(rename = "signerName")]
+    pub signer_name: Option<String>,
+    /// Backend that signs the certificate, defaults to the Kubernetes CSR API
+    #[serde(rename = "issuerRef")]
+    pub issuer_ref: Option<IssuerRef>,
+    /// Algorithm and size of the generated key, defaults to ECDSA P-256
+    #[serde(rename = "privateKey")]
+    pub private_key: Option<PrivateKey>,
+    /// IP address SANs, e.g. a Pod or ClusterIP reached directly
+    #[serde(rename = "ipAddresses")]
+    pub ip_addresses: Option<Vec<String>>,
+    /// URI SANs such as SPIFFE IDs
+    pub uris: Option<Vec<String>>,
+    /// Email address SANs
+    #[serde(rename = "emailAddresses")]
+    pub email_addresses: Option<Vec<String>>,
+    /// Add every in-cluster DNS name of the service as a SAN, defaults to `true`
+    #[serde(rename = "expandServiceNames")]
+    pub expand_service_names: Option<bool>,
+    /// Subject of the certificate, ignored by `kubernetes.io/kubelet-serving` which requires
+    /// a node identity
+    pub subject: Option<Subject>,
+    /// CSR usages such as `digital signature`, `server auth` or `client auth`, defaults to
+    /// the usages the signer expects
+    pub usages: Option<Vec<String>>,
+    /// Secret the certificate is written to, defaults to the name of the `Certificate`
+    #[serde(rename = "secretName")]
+    pub secret_name: Option<String>,
+    /// Labels and annotations kept on the Secret
+    #[serde(rename = "secretTemplate")]
+    pub secret_template: Option<SecretTemplate>,
+    /// Java keystores written next to the PEM files
+    pub keystores: Option<Keystores>,
+    #[serde(rename = "additionalOutputFormats")]
+    pub additional_output_formats: Option<Vec<OutputFormat>>,
+}
+
+/// Drops null and, as a result, empty object members
+fn remove_nulls(value: &mut serde_json::Value) {
+    if let serde_json::Value::Object(map) = value {
+        map.values_mut().for_each(remove_nulls);
+        map.retain(|_, v| !(v.is_null() || v.as_object().is_some_and(|o| o.is_empty())));
+    }
+}
+
+fn parse_duration(field: &str, value: &str) -> Result<Duration, String> {
+    humantime::parse_duration(value).map_err(|e| format!("invalid {} {}: {}", field, value, e))
+}
+
+impl CertificateSpec {
+    pub fn duration(&self) -> Result<Duration, String> {
+        let duration = match &self.duration {
+            Some(d) => parse_duration("duration", d)?,
+            None => DEFAULT_DURATION,
+        };
+        if duration > MAXIMUM_DURATION {
+            return Err(format!(
+                "duration must be at most {}",
+                humantime::format_duration(MAXIMUM_DURATION)
+            ));
+        }
+        Ok(duration)
+    }
+
+    pub fn renew_before(&self) -> Result<Option<Duration>, String> {
+        match &self.renew_before {
+            Some(d) => Ok(Some(parse_duration("renewBefore", d)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn mode(&self) -> CertificateMode {
+        self.mode.unwrap_or_default()
+    }
+
+    pub fn expand_service_names(&self) -> bool {
+        self.expand_service_names.unwrap_or(true)
+    }
+
+    /// The names the service is reachable under from inside the cluster, e.g.
+    /// `svc`, `svc.ns`, `svc.ns.svc` and `svc.ns.svc.cluster.local`
+    pub fn service_names(&self, namespace: &str, cluster_domain: &str) -> Vec<String> {
+        let service = match &self.service {
+            Some(service) => service.to_lowercase(),
+            None => return Vec::new(),
+        };
+        let namespace = namespace.to_lowercase();
+        vec![
+            service.clone(),
+            format!("{}.{}", service, namespace),
+            format!("{}.{}.svc", service, namespace),
+            format!("{}.{}.svc.{}", service, namespace, cluster_domain),
+        ]
+    }
+
+    /// Hash of the fields that change the issued certificate or the Secret holding it, so
+    /// a change to one of them triggers a new issuance. `renewBefore` and `rotationPolicy`
+    /// only matter at the next renewal and `secretTemplate` is re-applied in place, so they
+    /// are left out. Unset fields are dropped, keeping hashes stable when fields are added.
+    pub fn issuance_hash(&self) -> String {
+        let private_key = self.private_key.as_ref();
+        let mut fields = serde_json::json!({
+            "service": self.service,
+            "mode": self.mode,
+            "alt_names": self.alt_names,
+            "duration": self.duration,
+            "signerName": self.signer_name,
+            "issuerRef": self.issuer_ref,
+            "privateKey": {
+                "algorithm": private_key.and_then(|k| k.algorithm),
+                "size": private_key.and_then(|k| k.size),
+            },
+            "ipAddresses": self.ip_addresses,
+            "uris": self.uris,
+            "emailAddresses": self.email_addresses,
+            "expandServiceNames": self.expand_service_names,
+            "subject": self.subject,
+            "usages": self.usages,
+            "secretName": self.secret_name,
+            "keystores": self.keystores,
+            "additionalOutputFormats": self.additional_output_formats,
+        });
+        remove_nulls(&mut fields);
+        let json = serde_json::to_vec(&fields).unwrap_or_default();
+        format!("{:x}", Sha256::digest(json))
+    }
+
+    pub fn ip_addresses(&self) -> Result<Vec<IpAddr>, String> {
+        self.ip_addresses
+            .iter()
+            .flatten()
+            .map(|ip| {
+                ip.parse()
+                    .map_err(|_| format!("invalid ipAddresses entry {}", ip))
+            })
+            .collect()
+    }
+
+    /// Whether the certificate is requested from a signer the API server trusts, such as
+    /// `kubernetes.io/kube-apiserver-client`, without an `Issuer` that could pick another
+    fn requests_apiserver_client(&self) -> bool {
+        let csr_issuer =
+            !matches!(&self.issuer_ref, Some(r) if r.kind != IssuerKind::KubernetesCsr);
+        let signer_name = self.signer_name.as_deref().unwrap_or(match self.mode() {
+            CertificateMode::Client => "kubernetes.io/kube-apiserver-client",
+            CertificateMode::Server => "kubernetes.io/kubelet-serving",
+        });
+        csr_issuer && signer_name.starts_with("kubernetes.io/kube-apiserver-client")
+    }
+
+    pub fn validate(&self, namespace: &str) -> Result<(), String> {
+        let duration = self.duration()?;
+        match self.mode() {
+            CertificateMode::Server if self.service.is_none() => {
+                return Err("service is required for server certificates".into());
+            }
+            CertificateMode::Client => {
+                let common_name = self.subject.as_ref().and_then(|s| s.common_name.as_ref());
+                if common_name.is_none() {
+                    return Err("subject.commonName is required for client certificates".into());
+                }
+                if self.signer_name.as_deref() == Some("kubernetes.io/kubelet-serving") {
+                    return Err(
+                        "kubernetes.io/kubelet-serving cannot sign client certificates".into(),
+                    );
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(signer_name) = &self.signer_name {
+            check_signer_name(signer_name)?;
+        }
+
+        if self.requests_apiserver_client() {
+            let mut subject = self.subject.clone().unwrap_or_default();
+            subject.common_name = subject.common_name.or(self.service.clone());
+            subject.check_apiserver_identity(namespace)?;
+        }
+
+        if duration < MINIMUM_DURATION {
+            return Err(format!(
+                "duration must be at least {}",
+                humantime::format_duration(MINIMUM_DURATION)
+            ));
+        }
+
+        if let Some(renew_before) = self.renew_before()? {
+            if renew_before >= duration {
+                return Err("renewBefore must be shorter than duration".into());
+            }
+        }
+
+        if let Some(issuer_ref) = &self.issuer_ref {
+            let needs_name = matches!(
+                issuer_ref.kind,
+                IssuerKind::Ca | IssuerKind::Issuer | IssuerKind::ClusterIssuer
+            );
+            if needs_name && issuer_ref.name.is_none() {
+                return Err(format!(
+                    "issuerRef.name is required for {:?} issuers",
+                    issuer_ref.kind
+                ));
+            }
+            check_issuer_ref_namespace(issuer_ref, namespace)?;
+        }
+
+        if let Some(private_key) = &self.private_key {
+            private_key.validate()?;
+        }
+
+        if let Some(subject) = &self.subject {
+            subject.validate()?;
+        }
+
+        if let Some(usage) = self
+            .usages
+            .iter()
+            .flatten()
+            .find(|u| !USAGES.contains(&u.as_str()))
+        {
+            return Err(format!("unknown usage {}", usage));
+        }
+
+        self.ip_addresses()?;
+        if let Some(uri) = self.uris.iter().flatten().find(|u| !u.contains(':')) {
+            return Err(format!("invalid uris entry {}, a scheme is required", uri));
+        }
+        if let Some(email) = self
+            .email_addresses
+            .iter()
+            .flatten()
+            .find(|e| !e.contains('@'))
+        {
+            return Err(format!("invalid emailAddresses entry {}", email));
+        }
+
+        Ok(())
+    }
+}
+
+/// CA Secrets and `Issuer`s are only usable from their own namespace
+pub fn check_issuer_ref_namespace(issuer_ref: &IssuerRef, namespace: &str) -> Result<(), String> {
+    let namespaced = matches!(issuer_ref.kind, IssuerKind::Ca | IssuerKind::Issuer);
+    match issuer_ref.namespace.as_deref() {
+        Some(other) if namespaced && other != namespace => Err(format!(
+            "issuerRef.namespace {} must be the certificate's namespace, use a ClusterIssuer",
+            other
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn check_signer_name(signer_name: &str) -> Result<(), String> {
+    if signer_name == LEGACY_UNKNOWN_SIGNER {
+        return Err(format!("{} does not sign certificates", signer_name));
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct KubernetesIssuerConfig {
+    #[serde(rename = "signerName")]
+    pub signer_name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct CaIssuerConfig {
+    /// `kubernetes.io/tls` Secret holding the CA certificate and key
+    #[serde(rename = "secretName")]
+    pub secret_name: String,
+    /// Namespace of the Secret, must be the `Issuer`'s namespace if set and is required for
+    /// a `ClusterIssuer`
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
+pub struct SelfSignedIssuerConfig {}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+pub struct AcmeIssuerConfig {
+    /// ACME directory URL
+    pub server: String,
+    pub email: Option<String>,
+    /// Secret the account credentials are kept in, in the namespace of each `Certificate`
+    #[serde(rename = "accountSecretName")]
+    pub account_secret_name: Option<String>,
+    /// Ingress class that routes HTTP-01 challenges to the solver
+    #[serde(rename = "ingressClassName")]
+    pub ingress_class_name: Option<String>,
+    /// Image serving the challenge responses, must provide busybox `httpd`
+    #[serde(rename = "solverImage")]
+    pub solver_image: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema, Default)]
+pub struct IssuerStatus {
+    pub conditions: Option<Vec<CertificateCondition>>,
+}
+
+/// How certificates referencing this issuer get signed, exactly one backend must be set
+#[derive(CustomResource, Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+#[kube(
+    group = "certificate-helper.io",
+    version = "v1",
+    kind = "Issuer",
+    namespaced
+)]
+#[kube(singular = "issuer", plural = "issuers")]
+#[kube(status = "IssuerStatus")]
+pub struct IssuerSpec {
+    pub kubernetes: Option<KubernetesIssuerConfig>,
+    pub ca: Option<CaIssuerConfig>,
+    #[serde(rename = "selfSigned")]
+    pub self_signed: Option<SelfSignedIssuerConfig>,
+    pub acme: Option<AcmeIssuerConfig>,
+}
+
+impl IssuerSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(kubernetes) = &self.kubernetes {
+            check_signer_name(&kubernetes.signer_name)?;
+        }
+        let backends = [
+            self.kubernetes.is_some(),
+            self.ca.is_some(),
+            self.self_signed.is_some(),
+            self.acme.is_some(),
+        ];
+        match backends.iter().filter(|b| **b).count() {
+            1 => Ok(()),
+            0 => Err("one of kubernetes, ca, selfSigned or acme must be set".into()),
+            _ => Err("only one of kubernetes, ca, selfSigned or acme may be set".into()),
+        }
+    }
+}
+
+/// Cluster wide equivalent of an `Issuer`
+#[derive(CustomResource, Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+#[kube(
+    group = "certificate-helper.io",
+    version = "v1",
+    kind = "ClusterIssuer"
+)]
+#[kube(singular = "clusterissuer", plural = "clusterissuers")]
+#[kube(status = "IssuerStatus")]
+pub struct ClusterIssuerSpec {
+    #[serde(flatten)]
+    pub issuer: IssuerSpec,
+}
+
+impl Issuer {
+    /// A namespaced `Issuer` may only sign with a CA Secret from its own namespace
+    pub fn validate(&self) -> Result<(), String> {
+        self.spec.validate()?;
+        let namespace = self.metadata.namespace.as_deref().unwrap_or_default();
+        match self.spec.ca.as_ref().and_then(|ca| ca.namespace.as_deref()) {
+            Some(other) if other != namespace => Err(format!(
+                "ca.namespace {} must be the Issuer's namespace, use a ClusterIssuer",
+                other
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ClusterIssuerSpec {
+    pub fn validate(&self) -> Result<(), String> {
+        self.issuer.validate()?;
+        if let Some(ca) = &self.issuer.ca {
+            if ca.namespace.is_none() {
+                return Err("ca.namespace is required for a ClusterIssuer".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every CRD served by certificate-helper, with both `Certificate` versions merged and v2
+/// stored
+pub fn crds() -> Result<Vec<CustomResourceDefinition>, MergeError> {
+    let mut certificate = merge_crds(vec![v1::Certificate::crd(), Certificate::crd()], "v2")?;
+    for version in certificate.spec.versions.iter_mut() {
+        if version.name == "v1" {
+            version.deprecated = Some(true);
+            version.deprecation_warning =
+                Some("certificate-helper.io/v1 Certificate is deprecated, use v2".into());
+        }
+    }
+    Ok(vec![certificate, Issuer::crd(), ClusterIssuer::crd()])
+}
+
+/// The original `Certificate`, which named the Secret's namespace in `spec.namespace`. Still
+/// served for migration and converted to and from v2 by the webhook in `admission`. The
+/// namespace has to match the namespace of the object itself.
+pub mod v1 {
+    use kube::core::ResourceExt;
+    use kube::CustomResource;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    use super::CertificateStatus;
+
+    #[derive(CustomResource, Serialize, Deserialize, Clone, PartialEq, Debug, JsonSchema)]
+    #[kube(
+        group = "certificate-helper.io",
+        version = "v1",
+        kind = "Certificate",
+        namespaced
+    )]
+    #[kube(singular = "certificate", plural = "certificates")]
+    #[kube(shortname = "cert", shortname = "crt")]
+    #[kube(
+        printcolumn = r#"{"name":"Ready","type":"string","jsonPath":".status.conditions[?(@.type==\"Ready\")].status"}"#,
+        printcolumn = r#"{"name":"Secret","type":"string","jsonPath":".status.certificate"}"#,
+        printcolumn = r#"{"name":"NotAfter","type":"date","jsonPath":".status.notAfter"}"#,
+        printcolumn = r#"{"name":"Signer","type":"string","jsonPath":".spec.signerName"}"#,
+        printcolumn = r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#
+    )]
+    #[kube(status = "CertificateStatus")]
+    pub struct CertificateSpec {
+        pub namespace: String,
+        #[serde(flatten)]
+        pub spec: super::CertificateSpec,
+    }
+
+    impl CertificateSpec {
+        fn check_namespace(&self, namespace: Option<&str>) -> Result<(), String> {
+            if namespace != Some(self.namespace.as_str()) {
+                return Err(format!(
+                    "spec.namespace {} must match the namespace of the Certificate",
+                    self.namespace
+                ));
+            }
+            Ok(())
+        }
+
+        pub fn validate(&self, namespace: Option<&str>) -> Result<(), String> {
+            self.check_namespace(namespace)?;
+            self.spec.validate(&self.namespace)
+        }
+    }
+
+    impl TryFrom<Certificate> for super::Certificate {
+        type Error = String;
+
+        fn try_from(certificate: Certificate) -> Result<Self, Self::Error> {
+            certificate
+                .spec
+                .check_namespace(certificate.namespace().as_deref())?;
+            let mut converted =
+                super::Certificate::new(&certificate.name_any(), certificate.spec.spec);
+            converted.metadata = certificate.metadata;
+            converted.status = certificate.status;
+            Ok(converted)
+        }
+    }
+
+    impl From<super::Certificate> for Certificate {
+        fn from(certificate: super::Certificate) -> Self {
+            let name = certificate.name_any();
+            let spec = CertificateSpec {
+                namespace: certificate.namespace().unwrap_or_default(),
+                spec: certificate.spec,
+            };
+            let mut converted = Certificate::new(&name, spec);
+            converted.metadata = certificate.metadata;
+            converted.status = certificate.status;
+            converted
+        }
+    }
 }
@@ -0,0 +1,171 @@
+use futures::{join, StreamExt};
+use kube::api::{Patch, PatchParams};
+use kube::core::ResourceExt;
+use kube::runtime::controller::Action;
+use kube::runtime::{controller::Controller, watcher, Config};
+use kube::{Api, Client};
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::controller::Error;
+use crate::crd::{
+    set_condition, CertificateCondition, ClusterIssuer, Issuer, IssuerSpec, IssuerStatus,
+    CONDITION_READY,
+};
+use crate::operator::load_ca;
+
+/// How often issuers are re-checked, e.g. to notice a CA Secret being created or removed
+const RECHECK_INTERVAL: Duration = Duration::from_secs(300);
+
+struct IssuerContext {
+    kube: Client,
+}
+
+/// Checks that the configured backend is usable
+async fn check(client: Client, spec: &IssuerSpec, namespace: Option<&str>) -> Result<(), String> {
+    if let Some(ca) = &spec.ca {
+        let namespace = ca
+            .namespace
+            .as_deref()
+            .or(namespace)
+            .ok_or("ca.namespace is required")?;
+        load_ca(client, &ca.secret_name, namespace)
+            .await
+            .map_err(|e| format!("CA Secret {}/{} unusable: {}", namespace, ca.secret_name, e))?;
+    }
+    Ok(())
+}
+
+/// Builds the new status, or `None` when the Ready condition is unchanged
+fn ready_status(
+    current: &Option<IssuerStatus>,
+    generation: Option<i64>,
+    result: Result<(), String>,
+) -> Option<IssuerStatus> {
+    let condition = match result {
+        Ok(_) => CertificateCondition::new(
+            CONDITION_READY,
+            true,
+            "IssuerReady",
+            "Issuer is ready".into(),
+            generation,
+        ),
+        Err(reason) => {
+            CertificateCondition::new(CONDITION_READY, false, "IssuerNotReady", reason, generation)
+        }
+    };
+
+    let mut conditions = current
+        .as_ref()
+        .and_then(|s| s.conditions.clone())
+        .unwrap_or_default();
+    let unchanged = conditions.iter().any(|c| {
+        c.type__ == condition.type__
+            && c.status == condition.status
+            && c.message == condition.message
+            && c.observed_generation == condition.observed_generation
+    });
+    if unchanged {
+        return None;
+    }
+
+    set_condition(&mut conditions, condition);
+    Some(IssuerStatus {
+        conditions: Some(conditions),
+    })
+}
+
+async fn reconcile_issuer(issuer: Arc<Issuer>, ctx: Arc<IssuerContext>) -> Result<Action, Error> {
+    let namespace = issuer.namespace().unwrap_or("default".into());
+    let result = match issuer.validate() {
+        Ok(_) => check(ctx.kube.clone(), &issuer.spec, Some(&namespace)).await,
+        Err(reason) => Err(reason),
+    };
+
+    if let Some(status) = ready_status(&issuer.status, issuer.metadata.generation, result) {
+        let api: Api<Issuer> = Api::namespaced(ctx.kube.clone(), &namespace);
+        let patch = json!({ "status": status });
+        api.patch_status(
+            &issuer.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await?;
+        info!("Issuer {}/{} status updated", namespace, issuer.name_any());
+    }
+
+    Ok(Action::requeue(RECHECK_INTERVAL))
+}
+
+async fn reconcile_cluster_issuer(
+    issuer: Arc<ClusterIssuer>,
+    ctx: Arc<IssuerContext>,
+) -> Result<Action, Error> {
+    let result = match issuer.spec.validate() {
+        Ok(_) => check(ctx.kube.clone(), &issuer.spec.issuer, None).await,
+        Err(reason) => Err(reason),
+    };
+
+    if let Some(status) = ready_status(&issuer.status, issuer.metadata.generation, result) {
+        let api: Api<ClusterIssuer> = Api::all(ctx.kube.clone());
+        let patch = json!({ "status": status });
+        api.patch_status(
+            &issuer.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await?;
+        info!("ClusterIssuer {} status updated", issuer.name_any());
+    }
+
+    Ok(Action::requeue(RECHECK_INTERVAL))
+}
+
+fn error_policy<K: ResourceExt>(obj: Arc<K>, error: &Error, _ctx: Arc<IssuerContext>) -> Action {
+    warn!("Issuer {} received error {:?}", obj.name_any(), error);
+    Action::requeue(Duration::from_secs(60))
+}
+
+/// Keeps the Ready condition of every `Issuer` and `ClusterIssuer` up to date
+pub async fn run() -> Result<(), Error> {
+    let client = Client::try_default().await?;
+    let context = Arc::new(IssuerContext {
+        kube: client.clone(),
+    });
+
+    let issuers = Controller::new(
+        Api::<Issuer>::all(client.clone()),
+        watcher::Config::default(),
+    )
+    .with_config(Config::default().concurrency(2))
+    .shutdown_on_signal()
+    .run(reconcile_issuer, error_policy, context.clone())
+    .for_each(|res| async move {
+        match res {
+            Ok((o, _)) => info!("issuer reconcile complete for {}", o.name),
+            Err(e) => warn!("issuer reconcile failed: {:?}", e),
+        }
+    });
+
+    let cluster_issuers = Controller::new(
+        Api::<ClusterIssuer>::all(client.clone()),
+        watcher::Config::default(),
+    )
+    .with_config(Config::default().concurrency(2))
+    .shutdown_on_signal()
+    .run(reconcile_cluster_issuer, error_policy, context)
+    .for_each(|res| async move {
+        match res {
+            Ok((o, _)) => info!("cluster issuer reconcile complete for {}", o.name),
+            Err(e) => warn!("cluster issuer reconcile failed: {:?}", e),
+        }
+    });
+
+    join!(issuers, cluster_issuers);
+
+    println!("Issuer controller terminated");
+
+    Ok(())
+}
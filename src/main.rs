@@ -2,8 +2,10 @@ use clap::{Args, Parser};
 use futures::join;
 
 mod admission;
+mod ca_signer;
 mod controller;
 mod crd;
+mod issuer_controller;
 mod operator;
 
 #[derive(Parser)]
@@ -11,13 +13,37 @@ mod operator;
 #[command(bin_name = "certificate-helper")]
 enum CertificateHelperCli {
     Run(RunArgs),
+    /// Print the CustomResourceDefinitions as YAML, e.g. to regenerate certificate-helper.yaml
+    Crd,
 }
 
-#[derive(Args)]
+#[derive(Args, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct RunArgs {
     #[arg(short, long)]
     port: u16,
+    /// Fraction of a certificate's lifetime after which it is renewed
+    #[arg(long, default_value_t = 2.0 / 3.0, value_parser = parse_fraction)]
+    renewal_fraction: f64,
+    /// Secret holding the CA used to sign `certificate-helper.io/ca` requests
+    #[arg(long, default_value = "certificate-helper-ca")]
+    ca_secret_name: String,
+    #[arg(long, default_value = "certificate-helper")]
+    ca_secret_namespace: String,
+    /// DNS domain of the cluster, used to expand service names into SANs
+    #[arg(long, default_value = "cluster.local")]
+    cluster_domain: String,
+}
+
+fn parse_fraction(value: &str) -> Result<f64, String> {
+    let fraction: f64 = value
+        .parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    if fraction > 0.0 && fraction < 1.0 {
+        Ok(fraction)
+    } else {
+        Err(format!("{} is not between 0 and 1", fraction))
+    }
 }
 
 /// something to drive the controller
@@ -27,10 +53,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     match CertificateHelperCli::parse() {
         CertificateHelperCli::Run(args) => {
             let adm_proc = admission::serve(args.port);
-            let controller_proc = controller::run();
-            let (adm_result, controller_result) = join!(adm_proc, controller_proc);
+            let controller_proc = controller::run(args.clone());
+            let signer_proc = ca_signer::run(args.clone());
+            let issuer_proc = issuer_controller::run();
+            let (adm_result, controller_result, signer_result, issuer_result) =
+                join!(adm_proc, controller_proc, signer_proc, issuer_proc);
             adm_result?;
             controller_result?;
+            signer_result?;
+            issuer_result?;
+        }
+        CertificateHelperCli::Crd => {
+            for crd in crd::crds()? {
+                print!("---\n{}", serde_yaml::to_string(&crd)?);
+            }
         }
     };
 
@@ -1,71 +1,169 @@
-use k8s_openapi::api::certificates::v1::{
-    CertificateSigningRequest, CertificateSigningRequestCondition, CertificateSigningRequestSpec,
-    CertificateSigningRequestStatus,
-};
+use chrono::offset::Utc;
+use chrono::SecondsFormat;
 use k8s_openapi::api::core::v1::Secret;
-use k8s_openapi::apimachinery::pkg::apis::meta::v1::Time;
 use k8s_openapi::ByteString;
+use kube::api::{DeleteParams, Patch, PatchParams};
+use kube::core::ObjectMeta;
 use kube::core::ResourceExt;
-use kube::Client;
-use kube::{core::ObjectMeta, Api};
-use rcgen::{date_time_ymd, Certificate, CertificateParams, DistinguishedName, DnType, SanType};
+use kube::{Api, Client, Resource};
+use rcgen::{CertificateParams, KeyPair, SanType};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
 use std::collections::BTreeMap;
+use std::time::Duration;
+use time::OffsetDateTime;
 use tracing::info;
 
 use crate::controller::Error;
-use crate::crd::{Certificate as CertificateHelper, Stage};
+use crate::crd::{
+    Certificate as CertificateHelper, CertificateMode, CertificateStatus, OutputFormat, PrivateKey,
+    RotationPolicy, SecretKeySelector, SecretTemplate, Stage,
+};
+use crate::RunArgs;
+
+use super::issuer::{CertificateRequest, IssuedCertificate, ResolvedIssuer};
+use super::key::{describe, generate_key_pair};
+use super::keystore::{
+    jks_keystore, jks_truststore, pem_certificates, pkcs12_keystore, pkcs12_truststore,
+};
+use super::signer::{apply_usages, client_usages};
+use super::x509::parse_certificate;
+use super::{perform_get, perform_operation, update_status, Operation};
+
+/// The SAN as written in the status, e.g. `my-service.my-namespace` or `10.0.0.1`
+fn san_name(san: &SanType) -> String {
+    match san {
+        SanType::DnsName(name) | SanType::URI(name) | SanType::Rfc822Name(name) => name.clone(),
+        SanType::IpAddress(ip) => ip.to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+const TLS_SECRET_TYPE: &str = "kubernetes.io/tls";
+/// Hash of the spec the certificate in a Secret was issued for, see `issuance_hash`
+const SPEC_HASH_ANNOTATION: &str = "certificate-helper.io/spec-hash";
+/// Hash of the data written to a Secret, see `data_hash`
+const DATA_HASH_ANNOTATION: &str = "certificate-helper.io/data-hash";
+/// Label and annotation keys set from `secretTemplate`, so keys dropped from the template
+/// can be removed from the Secret
+const TEMPLATE_KEYS_ANNOTATION: &str = "certificate-helper.io/template-keys";
+/// UID of the `Certificate` a Secret belongs to, used to watch managed Secrets. The UID
+/// rather than the name is used as names can exceed the 63 characters of a label value.
+pub const CERTIFICATE_LABEL: &str = "certificate-helper.io/certificate";
+
+#[derive(Serialize, Deserialize, Default)]
+struct TemplateKeys {
+    labels: Vec<String>,
+    annotations: Vec<String>,
+}
+
+impl TemplateKeys {
+    fn from_template(template: &SecretTemplate) -> TemplateKeys {
+        let keys = |map: &Option<BTreeMap<String, String>>| {
+            map.iter().flatten().map(|(k, _)| k.clone()).collect()
+        };
+        TemplateKeys {
+            labels: keys(&template.labels),
+            annotations: keys(&template.annotations),
+        }
+    }
+
+    fn from_secret(secret: &Secret) -> TemplateKeys {
+        secret
+            .annotations()
+            .get(TEMPLATE_KEYS_ANNOTATION)
+            .and_then(|keys| serde_json::from_str(keys).ok())
+            .unwrap_or_default()
+    }
+}
 
-use super::{perform_cluster_operation, perform_operation};
-use super::{perform_get, update_status, Operation};
+/// Secret keys that follow the issuing CA and are updated in place by `sync_ca`
+const CA_KEYS: [&str; 3] = ["ca.crt", "truststore.p12", "truststore.jks"];
+
+/// Hash of the Secret data, leaving out the `CA_KEYS`
+fn data_hash(data: &BTreeMap<String, ByteString>) -> String {
+    let mut hasher = Sha256::new();
+    for (key, value) in data
+        .iter()
+        .filter(|(key, _)| !CA_KEYS.contains(&key.as_str()))
+    {
+        hasher.update(key.as_bytes());
+        hasher.update([0]);
+        hasher.update(&value.0);
+        hasher.update([0]);
+    }
+    format!("{:x}", hasher.finalize())
+}
 
 pub struct CertificateStage {
     client: Client,
+    args: RunArgs,
     operation: Operation,
     certificate: CertificateHelper,
-    cert: Option<Certificate>,
-    csr_request: Option<CertificateSigningRequest>,
-    signed_cert: Option<ByteString>,
-    cert_creation_time: Option<Time>,
+    /// Namespace of the `Certificate`, which its Secret is written to
+    namespace: String,
+    issuer: Option<ResolvedIssuer>,
+    params: Option<CertificateParams>,
+    private_key: Option<String>,
+    alt_names: Vec<String>,
+    usages: Vec<String>,
+    /// Set when a new key was generated rather than reused
+    key_algorithm: Option<String>,
+    issued: Option<IssuedCertificate>,
     secret: Option<Secret>,
 }
 
 impl CertificateStage {
     pub fn new(
         client: Client,
+        args: RunArgs,
         operation: Operation,
         certificate: CertificateHelper,
     ) -> CertificateStage {
         CertificateStage {
             client,
+            args,
             operation,
+            namespace: certificate.namespace().unwrap_or_default(),
             certificate,
-            cert: None,
-            csr_request: None,
-            signed_cert: None,
-            cert_creation_time: None,
+            issuer: None,
+            params: None,
+            private_key: None,
+            alt_names: Vec::new(),
+            usages: Vec::new(),
+            key_algorithm: None,
+            issued: None,
             secret: None,
         }
     }
 
     pub async fn run(&mut self) -> Result<(), Error> {
         match self.operation {
-            Operation::Create => {
-                self.generate_cert().await?;
-                self.create_csr().await?;
-                self.approve_csr().await?;
-                self.create_secret().await?;
-                self.delete_csr().await?;
+            Operation::Create | Operation::Update => {
+                update_status(
+                    self.client.clone(),
+                    Stage::Creating,
+                    self.certificate.clone(),
+                )
+                .await?;
+                if let Err(e) = self.issue_and_store().await {
+                    update_status(
+                        self.client.clone(),
+                        Stage::CreationFailed(e.to_string()),
+                        self.certificate.clone(),
+                    )
+                    .await?;
+                    return Err(e);
+                }
             }
             Operation::Delete => {
                 if let Some(status) = self.certificate.status.clone() {
                     if let Some(secret) = status.certificate {
-                        let secret: Secret = perform_get(
-                            self.client.clone(),
-                            &secret,
-                            &self.certificate.spec.namespace,
-                        )
-                        .await?;
-                        self.secret = Some(secret);
+                        // Already gone, e.g. when the whole namespace is being deleted
+                        let api: Api<Secret> =
+                            Api::namespaced(self.client.clone(), &self.namespace);
+                        self.secret = api.get_opt(&secret).await?;
                     };
                 };
                 self.delete().await?;
@@ -74,169 +172,686 @@ impl CertificateStage {
             _ => {}
         };
 
-        let csr_name = match self.csr_request.clone() {
-            Some(csr) => csr.name_any(),
-            None => "<unknown>".into(),
-        };
         update_status(
             self.client.clone(),
-            Stage::CertificateCreated(csr_name),
+            Stage::CertificateCreated(self.secret_name()),
             self.certificate.clone(),
         )
         .await?;
+        self.record_issuance().await?;
+        Ok(())
+    }
+
+    async fn issue_and_store(&mut self) -> Result<(), Error> {
+        self.issuer = Some(ResolvedIssuer::resolve(self.client.clone(), &self.certificate).await?);
+        self.generate_cert().await?;
+        self.issue().await?;
+        self.create_secret().await
+    }
 
-        if let Some(uid) = self.certificate.uid() {
-            if let Some(secret) = self.secret.clone() {
-                perform_operation(self.client.clone(), Operation::ApplyOwner(uid), &secret).await?;
-            };
+    /// Reads the issued certificate back from the Secret and works out how long until it
+    /// is due for renewal. Returns `None` when renewal is already due.
+    pub async fn time_until_renewal(&self, fraction: f64) -> Result<Option<Duration>, Error> {
+        let secret_name = match self.certificate.status.clone() {
+            Some(status) => match status.certificate {
+                Some(secret_name) => secret_name,
+                None => return Ok(None),
+            },
+            None => return Ok(None),
         };
-        Ok(())
+
+        let secret: Secret =
+            perform_get(self.client.clone(), &secret_name, &self.namespace).await?;
+
+        let cert = match secret.data.as_ref().and_then(|d| d.get("tls.crt")) {
+            Some(cert) => cert.0.clone(),
+            None => return Ok(None),
+        };
+
+        let renew_before = self
+            .certificate
+            .spec
+            .renew_before()
+            .map_err(Error::InvalidSpec)?;
+        let renewal_time = parse_certificate(&cert)?.renewal_time(fraction, renew_before);
+        let remaining = renewal_time - Utc::now();
+        Ok(remaining.to_std().ok().filter(|d| !d.is_zero()))
     }
 
     async fn generate_cert(&mut self) -> Result<(), Error> {
+        let duration = self
+            .certificate
+            .spec
+            .duration()
+            .map_err(Error::InvalidSpec)?;
+        let signer = self.issuer.as_ref().unwrap().signer();
         let mut params: CertificateParams = Default::default();
-        params.not_before = date_time_ymd(1975, 1, 1);
-        params.not_after = date_time_ymd(4096, 1, 1);
-        params.distinguished_name = DistinguishedName::new();
-        params
-            .distinguished_name
-            .push(DnType::OrganizationName, "system:nodes");
-        params.distinguished_name.push(
-            DnType::CommonName,
-            format!(
-                "system:node:{}",
-                self.certificate.spec.service.to_lowercase()
-            ),
-        );
+        params.not_before = OffsetDateTime::now_utc();
+        params.not_after = params.not_before + duration;
+        self.usages =
+            self.certificate
+                .spec
+                .usages
+                .clone()
+                .unwrap_or(match self.certificate.spec.mode() {
+                    CertificateMode::Client => client_usages(),
+                    CertificateMode::Server => signer.usages(),
+                });
+        apply_usages(&mut params, &self.usages);
+        let service = self
+            .certificate
+            .spec
+            .service
+            .as_ref()
+            .map(|s| s.to_lowercase());
+        // CSRs are approved by the operator, so the subject is checked before one is created
+        params.distinguished_name = signer
+            .distinguished_name(
+                &self.namespace,
+                service.as_deref(),
+                self.certificate.spec.subject.as_ref(),
+            )
+            .map_err(Error::InvalidSpec)?;
 
-        let mut alt_names = vec![SanType::DnsName(
-            self.certificate.spec.service.to_lowercase(),
-        )];
+        let resolved = self.issuer.as_ref().unwrap();
+        // ACME orders only carry DNS names, a CSR with other SANs would be refused at
+        // finalization after the challenges were already solved
+        if resolved.is_public() {
+            let spec = &self.certificate.spec;
+            let unsupported = [
+                ("ipAddresses", &spec.ip_addresses),
+                ("uris", &spec.uris),
+                ("emailAddresses", &spec.email_addresses),
+            ];
+            if let Some((field, _)) = unsupported
+                .iter()
+                .find(|(_, values)| values.as_ref().is_some_and(|v| !v.is_empty()))
+            {
+                return Err(Error::InvalidSpec(format!(
+                    "{} are not supported by ACME issuers",
+                    field
+                )));
+            }
+        }
+        let mut alt_names = Vec::new();
+        if self.certificate.spec.expand_service_names() && !resolved.is_public() {
+            for name in self
+                .certificate
+                .spec
+                .service_names(&self.namespace, &self.args.cluster_domain)
+            {
+                alt_names.push(SanType::DnsName(name));
+            }
+        } else if let Some(service) = service {
+            alt_names.push(SanType::DnsName(service));
+        }
         for i in self.certificate.spec.alt_names.clone().unwrap_or_default() {
             alt_names.push(SanType::DnsName(i));
         }
+        for ip in self
+            .certificate
+            .spec
+            .ip_addresses()
+            .map_err(Error::InvalidSpec)?
+        {
+            alt_names.push(SanType::IpAddress(ip));
+        }
+        for uri in self.certificate.spec.uris.clone().unwrap_or_default() {
+            alt_names.push(SanType::URI(uri));
+        }
+        for email in self
+            .certificate
+            .spec
+            .email_addresses
+            .clone()
+            .unwrap_or_default()
+        {
+            alt_names.push(SanType::Rfc822Name(email));
+        }
 
-        params.subject_alt_names = alt_names;
-        self.cert = Some(Certificate::from_params(params)?);
-        Ok(())
-    }
+        let mut unique = Vec::new();
+        for name in alt_names {
+            if !unique.contains(&name) {
+                unique.push(name);
+            }
+        }
+        self.alt_names = unique.iter().map(san_name).collect();
+        params.subject_alt_names = unique;
 
-    async fn create_csr(&mut self) -> Result<(), Error> {
-        let raw_csr = self.cert.as_ref().unwrap().serialize_request_pem()?;
-        let request = CertificateSigningRequest {
-            metadata: ObjectMeta {
-                name: Some(self.certificate.name_any().to_lowercase()),
-                ..Default::default()
-            },
-            spec: CertificateSigningRequestSpec {
-                expiration_seconds: Some(86400),
-                signer_name: "kubernetes.io/kubelet-serving".into(),
-                request: ByteString(raw_csr.into_bytes().to_vec()),
-                usages: Some(vec![
-                    "key encipherment".into(),
-                    "digital signature".into(),
-                    "server auth".into(),
-                ]),
-                ..Default::default()
-            },
-            ..Default::default()
+        let private_key = self
+            .certificate
+            .spec
+            .private_key
+            .clone()
+            .unwrap_or_default();
+        let key_pair = match self.existing_key(&private_key).await? {
+            Some(key_pair) => key_pair,
+            None => {
+                self.key_algorithm = Some(describe(&private_key));
+                generate_key_pair(&private_key).await?
+            }
         };
-        let _response =
-            perform_cluster_operation(self.client.clone(), Operation::Create, &request).await?;
-        self.csr_request = Some(request);
-        info!(
-            "Certificate {} created",
-            self.certificate.name_any().to_lowercase()
-        );
+        params.alg = key_pair.algorithm();
+        self.private_key = Some(key_pair.serialize_pem());
+        params.key_pair = Some(key_pair);
+        self.params = Some(params);
         Ok(())
     }
 
-    async fn approve_csr(&mut self) -> Result<(), Error> {
-        let csr_api: Api<CertificateSigningRequest> = Api::all(self.client.clone());
-        let mut request = self.csr_request.clone().unwrap();
-
-        request.status = Some(CertificateSigningRequestStatus {
-            certificate: None,
-            conditions: Some(vec![CertificateSigningRequestCondition {
-                type_: String::from("Approved"),
-                message: Some(String::from("Approved by webhook-helper")),
-                reason: Some(String::from("WebHelperApproved")),
-                status: String::from("True"),
-                last_transition_time: None,
-                last_update_time: self.cert_creation_time.clone(),
-            }]),
-        });
-
-        let body: Vec<u8> = serde_json::to_vec(&request)?;
-        let url = format!(
-            "/apis/certificates.k8s.io/v1/certificatesigningrequests/{}/approval",
-            self.certificate.name_any().to_lowercase()
-        );
-        let req = http::request::Request::put(url).body(body)?;
-
-        // Deserialize JSON response as a JSON value. Alternatively, a type that
-        // implements `Deserialize` can be used.
-        let resp = self
-            .client
-            .request::<CertificateSigningRequest>(req)
-            .await?;
+    /// The key in the current Secret when renewing with `rotationPolicy: Never`
+    async fn existing_key(&self, private_key: &PrivateKey) -> Result<Option<KeyPair>, Error> {
+        if !matches!(self.operation, Operation::Update)
+            || private_key.rotation_policy() != RotationPolicy::Never
+        {
+            return Ok(None);
+        }
 
-        // waiter for status ok
-        let mut cert_with_approval = csr_api.get_approval(resp.name_any().as_str()).await?;
-        loop {
-            if let Some(status) = cert_with_approval.status.clone() {
-                if let Some(certificate) = status.certificate.clone() {
-                    self.signed_cert = Some(certificate);
-                    break;
-                };
-            };
+        let secret_name = match self.certificate.status.as_ref() {
+            Some(CertificateStatus {
+                certificate: Some(secret_name),
+                ..
+            }) => secret_name,
+            _ => return Ok(None),
+        };
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        let secret = api.get_opt(secret_name).await?;
 
-            std::thread::sleep(std::time::Duration::from_secs(5));
-            cert_with_approval = csr_api.get_approval(resp.name_any().as_str()).await?;
+        match secret
+            .as_ref()
+            .and_then(|s| s.data.as_ref())
+            .and_then(|d| d.get("tls.key"))
+        {
+            Some(key) => {
+                let pem = String::from_utf8(key.0.clone())
+                    .map_err(|e| Error::UnableToGenerateKey(e.to_string()))?;
+                info!(
+                    "Reusing private key of {}",
+                    self.certificate.name_any().to_lowercase()
+                );
+                Ok(Some(KeyPair::from_pem(&pem)?))
+            }
+            None => Ok(None),
         }
+    }
 
-        info!(
-            "Certificate {} approved",
-            self.certificate.name_any().to_lowercase()
-        );
+    async fn issue(&mut self) -> Result<(), Error> {
+        let resolved = self.issuer.as_ref().unwrap();
+        let request = CertificateRequest {
+            name: self.certificate.name_any().to_lowercase(),
+            namespace: self.namespace.clone(),
+            params: self.params.take().unwrap(),
+            usages: self.usages.clone(),
+            duration: self
+                .certificate
+                .spec
+                .duration()
+                .map_err(Error::InvalidSpec)?,
+        };
+        let issuer = resolved.issuer(self.client.clone())?;
+        let mut issued = issuer.issue(request).await?;
+        if issued.ca.is_none() {
+            issued.ca = resolved
+                .ca_certificate(self.client.clone(), &self.namespace, &self.args)
+                .await?;
+        }
+        self.issued = Some(issued);
         Ok(())
     }
 
     async fn create_secret(&mut self) -> Result<(), Error> {
-        let key = self
-            .cert
-            .as_ref()
-            .unwrap()
-            .serialize_private_key_pem()
-            .as_bytes()
-            .to_vec();
-        let cert = ByteString(self.signed_cert.as_ref().unwrap().0.clone());
+        let key = self.private_key.clone().unwrap().into_bytes();
+        let cert = ByteString(
+            self.issued
+                .as_ref()
+                .unwrap()
+                .certificate
+                .clone()
+                .into_bytes(),
+        );
 
         let mut data: BTreeMap<String, ByteString> = BTreeMap::new();
         data.insert("tls.key".into(), ByteString(key));
         data.insert("tls.crt".into(), cert);
+        if let Some(ca) = self.issued.as_ref().and_then(|i| i.ca.clone()) {
+            data.insert("ca.crt".into(), ByteString(ca.into_bytes()));
+        }
+        self.write_output_formats(&mut data)?;
+        self.write_keystores(&mut data).await?;
 
+        let template = self
+            .certificate
+            .spec
+            .secret_template
+            .clone()
+            .unwrap_or_default();
+        let template_keys = serde_json::to_string(&TemplateKeys::from_template(&template))?;
+        let mut annotations = template.annotations.unwrap_or_default();
+        annotations.insert(
+            SPEC_HASH_ANNOTATION.into(),
+            self.certificate.spec.issuance_hash(),
+        );
+        annotations.insert(DATA_HASH_ANNOTATION.into(), data_hash(&data));
+        annotations.insert(TEMPLATE_KEYS_ANNOTATION.into(), template_keys);
+        let mut labels = template.labels.unwrap_or_default();
+        labels.insert(CERTIFICATE_LABEL.into(), self.certificate_uid());
         let secret = Secret {
-            type_: Some(format!(
-                "{}/tls",
-                self.certificate.name_any().to_lowercase()
-            )),
+            type_: Some(TLS_SECRET_TYPE.into()),
             metadata: ObjectMeta {
-                name: Some(self.certificate.name_any().to_lowercase().to_string()),
-                namespace: Some(self.certificate.spec.namespace.clone()),
+                name: Some(self.secret_name()),
+                namespace: Some(self.namespace.clone()),
+                labels: Some(labels),
+                annotations: Some(annotations),
+                // The Secret lives in the namespace of its namespaced `Certificate`, so the
+                // Certificate can own it and the garbage collector removes it along with it
+                owner_references: self
+                    .certificate
+                    .controller_owner_ref(&())
+                    .map(|owner| vec![owner]),
                 ..Default::default()
             },
             data: Some(data),
             ..Default::default()
         };
 
-        let result = perform_operation(self.client.clone(), Operation::Create, &secret).await?;
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        let operation = match api.get_opt(&self.secret_name()).await? {
+            // `secretName` may point at any Secret in the namespace, only ones written for
+            // this Certificate are replaced
+            Some(existing) if !self.owns(&existing) => {
+                return Err(Error::InvalidSpec(format!(
+                    "Secret {} already exists and does not belong to this Certificate",
+                    self.secret_name()
+                )));
+            }
+            // The type of a Secret is immutable, so Secrets written with the old
+            // `<name>/tls` type are recreated
+            Some(existing) if existing.type_ != secret.type_ => {
+                perform_operation(self.client.clone(), Operation::Delete, &existing).await?;
+                Operation::Create
+            }
+            Some(_) => Operation::Update,
+            None => Operation::Create,
+        };
+        let result = perform_operation(self.client.clone(), operation, &secret).await?;
         self.secret = Some(result);
 
-        info!(
-            "Secret {} created",
-            self.certificate.name_any().to_lowercase()
+        // Remove the previous Secret when `secretName` changed
+        if let Some(previous) = self
+            .certificate
+            .status
+            .as_ref()
+            .and_then(|s| s.certificate.clone())
+        {
+            if previous != self.secret_name() {
+                if let Some(existing) = api.get_opt(&previous).await? {
+                    if self.owns(&existing) {
+                        api.delete(&previous, &DeleteParams::default()).await?;
+                        info!("Secret {} removed", previous);
+                    }
+                }
+            }
+        }
+
+        info!("Secret {} written", self.secret_name());
+        Ok(())
+    }
+
+    /// Adds the encodings requested in `additionalOutputFormats`
+    fn write_output_formats(&self, data: &mut BTreeMap<String, ByteString>) -> Result<(), Error> {
+        let formats = self
+            .certificate
+            .spec
+            .additional_output_formats
+            .clone()
+            .unwrap_or_default();
+        let key = self.private_key.as_ref().unwrap();
+        for format in formats {
+            match format {
+                OutputFormat::CombinedPem => {
+                    let chain = &self.issued.as_ref().unwrap().certificate;
+                    let mut combined = chain.trim_end().to_string();
+                    combined.push('\n');
+                    combined.push_str(key);
+                    data.insert("tls-combined.pem".into(), ByteString(combined.into_bytes()));
+                }
+                OutputFormat::Der => {
+                    let der = KeyPair::from_pem(key)?.serialize_der();
+                    data.insert("key.der".into(), ByteString(der));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the keystores requested in `keystores`, rebuilt every time the certificate is
+    /// issued. Truststores are only written when the CA is known.
+    async fn write_keystores(&self, data: &mut BTreeMap<String, ByteString>) -> Result<(), Error> {
+        let keystores = match &self.certificate.spec.keystores {
+            Some(keystores) => keystores,
+            None => return Ok(()),
+        };
+        let issued = self.issued.as_ref().unwrap();
+        let key = KeyPair::from_pem(self.private_key.as_ref().unwrap())?.serialize_der();
+        let chain = pem_certificates(&issued.certificate)?;
+
+        if let Some(pkcs12) = &keystores.pkcs12 {
+            let password = self.keystore_password(&pkcs12.password_secret_ref).await?;
+            data.insert(
+                "keystore.p12".into(),
+                ByteString(pkcs12_keystore(&key, &chain, &password)?),
+            );
+        }
+
+        if let Some(jks) = &keystores.jks {
+            let password = self.keystore_password(&jks.password_secret_ref).await?;
+            data.insert(
+                "keystore.jks".into(),
+                ByteString(jks_keystore(&key, &chain, &password)),
+            );
+        }
+
+        if let Some(ca) = &issued.ca {
+            data.extend(self.truststores(ca).await?);
+        }
+        Ok(())
+    }
+
+    /// Truststores holding the PEM `ca` for each enabled keystore format
+    async fn truststores(&self, ca: &str) -> Result<BTreeMap<String, ByteString>, Error> {
+        let mut data = BTreeMap::new();
+        let keystores = match &self.certificate.spec.keystores {
+            Some(keystores) => keystores,
+            None => return Ok(data),
+        };
+        let ca = match pem_certificates(ca)?.into_iter().next() {
+            Some(ca) => ca,
+            None => return Ok(data),
+        };
+
+        if let Some(pkcs12) = &keystores.pkcs12 {
+            let password = self.keystore_password(&pkcs12.password_secret_ref).await?;
+            data.insert(
+                "truststore.p12".into(),
+                ByteString(pkcs12_truststore(&ca, &password)?),
+            );
+        }
+        if let Some(jks) = &keystores.jks {
+            let password = self.keystore_password(&jks.password_secret_ref).await?;
+            data.insert(
+                "truststore.jks".into(),
+                ByteString(jks_truststore(&ca, &password)),
+            );
+        }
+        Ok(data)
+    }
+
+    async fn keystore_password(&self, selector: &SecretKeySelector) -> Result<String, Error> {
+        let secret: Secret =
+            perform_get(self.client.clone(), &selector.name, &self.namespace).await?;
+        let password = secret
+            .data
+            .and_then(|d| d.get(&selector.key).cloned())
+            .ok_or(Error::UnableToCreateKeystore(format!(
+                "Secret {} has no key {}",
+                selector.name, selector.key
+            )))?;
+        String::from_utf8(password.0).map_err(|e| Error::UnableToCreateKeystore(e.to_string()))
+    }
+
+    /// Name of the Secret the certificate is written to
+    fn secret_name(&self) -> String {
+        self.certificate
+            .spec
+            .secret_name
+            .clone()
+            .unwrap_or(self.certificate.name_any().to_lowercase())
+    }
+
+    /// Why the current Secret no longer holds the certificate issued into it: it was
+    /// deleted, its key does not match the certificate or its data was edited. Secrets
+    /// written before the data hash was recorded are stamped with it once their key matches.
+    pub async fn secret_drift(&self) -> Result<Option<String>, Error> {
+        let secret_name = match self
+            .certificate
+            .status
+            .as_ref()
+            .and_then(|s| s.certificate.as_ref())
+        {
+            Some(secret_name) => secret_name,
+            None => return Ok(None),
+        };
+
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        let secret = match api.get_opt(secret_name).await? {
+            Some(secret) => secret,
+            None => return Ok(Some(format!("Secret {} is missing", secret_name))),
+        };
+        let data = secret.data.clone().unwrap_or_default();
+        let (cert, key) = match (data.get("tls.crt"), data.get("tls.key")) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => {
+                return Ok(Some(format!(
+                    "Secret {} has no tls.crt or tls.key",
+                    secret_name
+                )))
+            }
+        };
+        let key = std::str::from_utf8(&key.0)
+            .ok()
+            .and_then(|k| KeyPair::from_pem(k).ok());
+        let matches = match (parse_certificate(&cert.0), key) {
+            (Ok(info), Some(key)) => info.public_key == key.public_key_raw(),
+            _ => false,
+        };
+        if !matches {
+            return Ok(Some(format!(
+                "tls.key in Secret {} does not match tls.crt",
+                secret_name
+            )));
+        }
+
+        let hash = data_hash(&data);
+        match secret.annotations().get(DATA_HASH_ANNOTATION) {
+            Some(written) if *written != hash => {
+                Ok(Some(format!("Secret {} was modified", secret_name)))
+            }
+            Some(_) => Ok(None),
+            None => {
+                let patch = json!({
+                    "metadata": {
+                        "labels": { CERTIFICATE_LABEL: self.certificate_uid() },
+                        "annotations": { DATA_HASH_ANNOTATION: hash },
+                    }
+                });
+                api.patch(secret_name, &PatchParams::default(), &Patch::Merge(&patch))
+                    .await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Whether the spec changed since the certificate in the current Secret was issued.
+    /// Secrets written before the hash was recorded are stamped with the current one rather
+    /// than reissued.
+    pub async fn spec_changed(&self) -> Result<bool, Error> {
+        let secret_name = match self
+            .certificate
+            .status
+            .as_ref()
+            .and_then(|s| s.certificate.as_ref())
+        {
+            Some(secret_name) => secret_name,
+            None => return Ok(false),
+        };
+
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        let secret = match api.get_opt(secret_name).await? {
+            Some(secret) => secret,
+            None => return Ok(false),
+        };
+        let hash = self.certificate.spec.issuance_hash();
+        match secret.annotations().get(SPEC_HASH_ANNOTATION) {
+            Some(issued) => Ok(*issued != hash),
+            None => {
+                let patch = json!({
+                    "metadata": { "annotations": { SPEC_HASH_ANNOTATION: hash } }
+                });
+                api.patch(secret_name, &PatchParams::default(), &Patch::Merge(&patch))
+                    .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Updates `ca.crt` and the truststores in the current Secret when the issuing CA has
+    /// rotated
+    pub async fn sync_ca(&self) -> Result<(), Error> {
+        let secret_name = match self
+            .certificate
+            .status
+            .as_ref()
+            .and_then(|s| s.certificate.as_ref())
+        {
+            Some(secret_name) => secret_name,
+            None => return Ok(()),
+        };
+
+        let resolved = ResolvedIssuer::resolve(self.client.clone(), &self.certificate).await?;
+        let ca = match resolved
+            .ca_certificate(self.client.clone(), &self.namespace, &self.args)
+            .await?
+        {
+            Some(ca) => ca,
+            None => return Ok(()),
+        };
+
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        let current = api
+            .get_opt(secret_name)
+            .await?
+            .and_then(|s| s.data)
+            .and_then(|d| d.get("ca.crt").cloned());
+        if current.as_ref().map(|c| c.0.as_slice()) == Some(ca.as_bytes()) {
+            return Ok(());
+        }
+
+        // The truststores are rebuilt along with `ca.crt` so JVM clients trust the new CA
+        let patch = json!({
+            "stringData": { "ca.crt": ca },
+            "data": self.truststores(&ca).await?,
+        });
+        api.patch(secret_name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await?;
+        info!("Secret {} ca.crt updated", secret_name);
+        Ok(())
+    }
+
+    /// Brings the labels and annotations of the current Secret in line with `secretTemplate`
+    pub async fn apply_secret_template(&self) -> Result<(), Error> {
+        let secret_name = match self
+            .certificate
+            .status
+            .as_ref()
+            .and_then(|s| s.certificate.as_ref())
+        {
+            Some(secret_name) => secret_name,
+            None => return Ok(()),
+        };
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), &self.namespace);
+        let secret = match api.get_opt(secret_name).await? {
+            Some(secret) => secret,
+            None => return Ok(()),
+        };
+        let template = self
+            .certificate
+            .spec
+            .secret_template
+            .clone()
+            .unwrap_or_default();
+        let previous = TemplateKeys::from_secret(&secret);
+        let current = TemplateKeys::from_template(&template);
+        if self.certificate.spec.secret_template.is_none()
+            && previous.labels.is_empty()
+            && previous.annotations.is_empty()
+        {
+            return Ok(());
+        }
+
+        // Keys dropped from the template are removed by setting them to null
+        let merge = |previous: &[String], current: Option<BTreeMap<String, String>>| {
+            let mut values: BTreeMap<String, Option<String>> = previous
+                .iter()
+                .filter(|key| ![SPEC_HASH_ANNOTATION, DATA_HASH_ANNOTATION].contains(&key.as_str()))
+                .map(|key| (key.clone(), None))
+                .collect();
+            values.extend(current.into_iter().flatten().map(|(k, v)| (k, Some(v))));
+            values
+        };
+        let mut labels = merge(&previous.labels, template.labels);
+        // The template may not take over the label managed Secrets are watched by
+        labels.insert(CERTIFICATE_LABEL.into(), Some(self.certificate_uid()));
+        let mut annotations = merge(&previous.annotations, template.annotations);
+        annotations.insert(
+            TEMPLATE_KEYS_ANNOTATION.into(),
+            Some(serde_json::to_string(&current)?),
         );
+        let patch = json!({
+            "metadata": {
+                "labels": labels,
+                "annotations": annotations,
+            }
+        });
+        api.patch(secret_name, &PatchParams::default(), &Patch::Merge(&patch))
+            .await?;
+        Ok(())
+    }
+
+    fn certificate_uid(&self) -> String {
+        self.certificate.uid().unwrap_or_default()
+    }
+
+    /// Whether the Secret was written for this Certificate: labelled with its UID, or owned
+    /// by it like the `<name>/tls` Secrets of earlier versions
+    fn owns(&self, secret: &Secret) -> bool {
+        let uid = self.certificate_uid();
+        !uid.is_empty()
+            && (secret.labels().get(CERTIFICATE_LABEL) == Some(&uid)
+                || secret.owner_references().iter().any(|o| o.uid == uid))
+    }
+
+    /// Records details of the newly issued certificate in the status
+    async fn record_issuance(&self) -> Result<(), Error> {
+        let issued = self.issued.as_ref().unwrap();
+        let info = parse_certificate(issued.certificate.as_bytes())?;
+        let renew_before = self
+            .certificate
+            .spec
+            .renew_before()
+            .map_err(Error::InvalidSpec)?;
+        let renewal_time = info.renewal_time(self.args.renewal_fraction, renew_before);
+
+        let mut status = json!({
+            "alt_names": self.alt_names,
+            "notBefore": info.not_before.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "notAfter": info.not_after.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "renewalTime": renewal_time.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "serialNumber": info.serial_number,
+            "fingerprintSHA256": info.fingerprint_sha256,
+            "issuer": info.issuer,
+            "observedGeneration": self.certificate.metadata.generation,
+        });
+        if let Some(key_algorithm) = &self.key_algorithm {
+            status["keyAlgorithm"] = json!(key_algorithm);
+        }
+        let patch = json!({ "status": status });
+
+        let api: Api<CertificateHelper> = Api::namespaced(self.client.clone(), &self.namespace);
+        api.patch_status(
+            &self.certificate.name_any(),
+            &PatchParams::default(),
+            &Patch::Merge(&patch),
+        )
+        .await?;
         Ok(())
     }
 
@@ -251,14 +866,4 @@ impl CertificateStage {
         };
         Ok(())
     }
-
-    async fn delete_csr(&mut self) -> Result<(), Error> {
-        if let Some(csr_request) = self.csr_request.clone() {
-            let _response =
-                perform_cluster_operation(self.client.clone(), Operation::Delete, &csr_request)
-                    .await?;
-        };
-        info!("CSR {} deleted", self.certificate.name_any().to_lowercase());
-        Ok(())
-    }
 }
@@ -0,0 +1,379 @@
+use async_trait::async_trait;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType, Identifier, NewAccount,
+    NewOrder, Order, OrderStatus,
+};
+use k8s_openapi::api::core::v1::{
+    Container, ContainerPort, Pod, PodSpec, Secret, Service, ServicePort, ServiceSpec,
+};
+use k8s_openapi::api::networking::v1::{
+    HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressRule,
+    IngressServiceBackend, IngressSpec, ServiceBackendPort,
+};
+use k8s_openapi::apimachinery::pkg::util::intstr::IntOrString;
+use k8s_openapi::ByteString;
+use kube::core::{ObjectMeta, ResourceExt};
+use kube::{Api, Client};
+use rcgen::{Certificate, SanType};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+use crate::controller::Error;
+use crate::crd::AcmeIssuerConfig;
+
+use super::super::signer::clear_usages;
+use super::super::{perform_operation, Operation};
+use super::{CertificateRequest, IssuedCertificate, Issuer};
+
+/// Records which server the stored account belongs to, so changing `server` registers anew
+const ACME_SERVER_ANNOTATION: &str = "certificate-helper.io/acme-server";
+const DEFAULT_ACCOUNT_SECRET: &str = "certificate-helper-acme-account";
+const DEFAULT_SOLVER_IMAGE: &str = "busybox:1.36";
+const SOLVER_PORT: i32 = 8089;
+const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+/// How many times the solver Pod and the order are polled before giving up
+const POLL_ATTEMPTS: u32 = 30;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Name of the solver Pod, Service and Ingress, also used as their label value. Certificate
+/// names may contain dots or be too long for a Service or label, so a short hash is used.
+fn solver_name(namespace: &str, name: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(format!("{}/{}", namespace, name)));
+    format!("acme-solver-{}", &hash[..16])
+}
+
+/// A pending HTTP-01 challenge and the response the solver has to serve for it
+struct Http01Challenge {
+    host: String,
+    token: String,
+    key_authorization: String,
+    url: String,
+}
+
+/// Issues certificates from an ACME server such as Let's Encrypt, solving HTTP-01
+/// challenges with a temporary solver Pod, Service and Ingress
+pub struct AcmeIssuer {
+    client: Client,
+    config: AcmeIssuerConfig,
+}
+
+impl AcmeIssuer {
+    pub fn new(client: Client, config: AcmeIssuerConfig) -> AcmeIssuer {
+        AcmeIssuer { client, config }
+    }
+
+    /// Loads the account from its Secret, registering a new one the first time
+    async fn account(&self, namespace: &str) -> Result<Account, Error> {
+        let secret_name = self
+            .config
+            .account_secret_name
+            .as_deref()
+            .unwrap_or(DEFAULT_ACCOUNT_SECRET);
+        let api: Api<Secret> = Api::namespaced(self.client.clone(), namespace);
+
+        let existing = api.get_opt(secret_name).await?;
+        let stored = existing
+            .as_ref()
+            .filter(|s| s.annotations().get(ACME_SERVER_ANNOTATION) == Some(&self.config.server))
+            .and_then(|s| s.data.as_ref())
+            .and_then(|d| d.get("credentials.json"));
+        if let Some(credentials) = stored {
+            let credentials: AccountCredentials = serde_json::from_slice(&credentials.0)?;
+            return Ok(Account::from_credentials(credentials).await?);
+        }
+
+        let contact = self.config.email.as_ref().map(|e| format!("mailto:{}", e));
+        let contact: Vec<&str> = contact.iter().map(|c| c.as_str()).collect();
+        let (account, credentials) = Account::create(
+            &NewAccount {
+                contact: &contact,
+                terms_of_service_agreed: true,
+                only_return_existing: false,
+            },
+            &self.config.server,
+            None,
+        )
+        .await?;
+
+        let mut data: BTreeMap<String, ByteString> = BTreeMap::new();
+        data.insert(
+            "credentials.json".into(),
+            ByteString(serde_json::to_vec(&credentials)?),
+        );
+        let secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(secret_name.into()),
+                namespace: Some(namespace.into()),
+                annotations: Some(BTreeMap::from([(
+                    ACME_SERVER_ANNOTATION.to_string(),
+                    self.config.server.clone(),
+                )])),
+                ..Default::default()
+            },
+            data: Some(data),
+            ..Default::default()
+        };
+        let operation = match existing {
+            Some(_) => Operation::Update,
+            None => Operation::Create,
+        };
+        perform_operation(self.client.clone(), operation, &secret).await?;
+        info!(
+            "Registered ACME account with {} in {}/{}",
+            self.config.server, namespace, secret_name
+        );
+        Ok(account)
+    }
+
+    async fn challenges(&self, order: &mut Order) -> Result<Vec<Http01Challenge>, Error> {
+        let mut challenges = Vec::new();
+        for authz in order.authorizations().await? {
+            match authz.status {
+                AuthorizationStatus::Pending => {}
+                AuthorizationStatus::Valid => continue,
+                status => {
+                    return Err(Error::AcmeOrderFailed(format!(
+                        "ACME authorization is {:?}",
+                        status
+                    )))
+                }
+            }
+
+            let Identifier::Dns(host) = authz.identifier;
+            let challenge = authz
+                .challenges
+                .iter()
+                .find(|c| c.r#type == ChallengeType::Http01)
+                .ok_or(Error::AcmeOrderFailed(format!(
+                    "no HTTP-01 challenge offered for {}",
+                    host
+                )))?;
+            challenges.push(Http01Challenge {
+                host,
+                token: challenge.token.clone(),
+                key_authorization: order.key_authorization(challenge).as_str().into(),
+                url: challenge.url.clone(),
+            });
+        }
+        Ok(challenges)
+    }
+
+    /// Pod serving every challenge response, with the Service and Ingress routing to it
+    fn solver(
+        &self,
+        name: &str,
+        namespace: &str,
+        challenges: &[Http01Challenge],
+    ) -> (Pod, Service, Ingress) {
+        let labels = BTreeMap::from([("certificate-helper.io/acme-solver".into(), name.into())]);
+        let metadata = ObjectMeta {
+            name: Some(name.into()),
+            namespace: Some(namespace.into()),
+            labels: Some(labels.clone()),
+            ..Default::default()
+        };
+
+        let mut script = format!("mkdir -p /www{}", CHALLENGE_PATH);
+        for challenge in challenges {
+            script.push_str(&format!(
+                " && printf '%s' '{}' > /www{}{}",
+                challenge.key_authorization, CHALLENGE_PATH, challenge.token
+            ));
+        }
+        script.push_str(&format!(" && exec httpd -f -p {} -h /www", SOLVER_PORT));
+
+        let pod = Pod {
+            metadata: metadata.clone(),
+            spec: Some(PodSpec {
+                containers: vec![Container {
+                    name: "acme-solver".into(),
+                    image: Some(
+                        self.config
+                            .solver_image
+                            .clone()
+                            .unwrap_or(DEFAULT_SOLVER_IMAGE.into()),
+                    ),
+                    command: Some(vec!["sh".into(), "-c".into(), script]),
+                    ports: Some(vec![ContainerPort {
+                        container_port: SOLVER_PORT,
+                        ..Default::default()
+                    }]),
+                    ..Default::default()
+                }],
+                restart_policy: Some("Never".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let service = Service {
+            metadata: metadata.clone(),
+            spec: Some(ServiceSpec {
+                selector: Some(labels),
+                ports: Some(vec![ServicePort {
+                    port: SOLVER_PORT,
+                    target_port: Some(IntOrString::Int(SOLVER_PORT)),
+                    ..Default::default()
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let rules = challenges
+            .iter()
+            .map(|challenge| IngressRule {
+                host: Some(challenge.host.clone()),
+                http: Some(HTTPIngressRuleValue {
+                    paths: vec![HTTPIngressPath {
+                        path: Some(format!("{}{}", CHALLENGE_PATH, challenge.token)),
+                        path_type: "Exact".into(),
+                        backend: IngressBackend {
+                            service: Some(IngressServiceBackend {
+                                name: name.into(),
+                                port: Some(ServiceBackendPort {
+                                    number: Some(SOLVER_PORT),
+                                    ..Default::default()
+                                }),
+                            }),
+                            ..Default::default()
+                        },
+                    }],
+                }),
+            })
+            .collect();
+        let ingress = Ingress {
+            metadata,
+            spec: Some(IngressSpec {
+                ingress_class_name: self.config.ingress_class_name.clone(),
+                rules: Some(rules),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        (pod, service, ingress)
+    }
+
+    async fn wait_for_solver(&self, pod: &Pod) -> Result<(), Error> {
+        for _ in 0..POLL_ATTEMPTS {
+            let pod = perform_operation(self.client.clone(), Operation::Get, pod).await?;
+            let ready = pod
+                .status
+                .and_then(|s| s.conditions)
+                .is_some_and(|c| c.iter().any(|c| c.type_ == "Ready" && c.status == "True"));
+            if ready {
+                return Ok(());
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        Err(Error::AcmeOrderFailed(
+            "ACME solver Pod did not become ready".into(),
+        ))
+    }
+
+    async fn remove_solver(
+        &self,
+        pod: &Pod,
+        service: &Service,
+        ingress: &Ingress,
+    ) -> Result<(), Error> {
+        perform_operation(self.client.clone(), Operation::Delete, ingress).await?;
+        perform_operation(self.client.clone(), Operation::Delete, service).await?;
+        perform_operation(self.client.clone(), Operation::Delete, pod).await?;
+        Ok(())
+    }
+
+    /// Tells the server the challenges are ready and waits for it to validate them
+    async fn validate(
+        &self,
+        order: &mut Order,
+        pod: &Pod,
+        challenges: &[Http01Challenge],
+    ) -> Result<(), Error> {
+        self.wait_for_solver(pod).await?;
+        for challenge in challenges {
+            order.set_challenge_ready(&challenge.url).await?;
+        }
+
+        for _ in 0..POLL_ATTEMPTS {
+            let state = order.refresh().await?;
+            match state.status {
+                OrderStatus::Ready | OrderStatus::Valid => return Ok(()),
+                OrderStatus::Invalid => {
+                    return Err(Error::AcmeOrderFailed(format!(
+                        "ACME order invalid: {:?}",
+                        state.error
+                    )))
+                }
+                _ => sleep(POLL_INTERVAL).await,
+            }
+        }
+        Err(Error::AcmeOrderFailed(
+            "timed out waiting for ACME challenges".into(),
+        ))
+    }
+}
+
+#[async_trait]
+impl Issuer for AcmeIssuer {
+    async fn issue(&self, request: CertificateRequest) -> Result<IssuedCertificate, Error> {
+        let account = self.account(&request.namespace).await?;
+        let identifiers: Vec<Identifier> = request
+            .params
+            .subject_alt_names
+            .iter()
+            .filter_map(|san| match san {
+                SanType::DnsName(name) => Some(Identifier::Dns(name.clone())),
+                _ => None,
+            })
+            .collect();
+        let mut order = account
+            .new_order(&NewOrder {
+                identifiers: &identifiers,
+            })
+            .await?;
+
+        let challenges = self.challenges(&mut order).await?;
+        if !challenges.is_empty() {
+            let name = solver_name(&request.namespace, &request.name);
+            let (pod, service, ingress) = self.solver(&name, &request.namespace, &challenges);
+            let pod = perform_operation(self.client.clone(), Operation::Create, &pod).await?;
+            let service =
+                perform_operation(self.client.clone(), Operation::Create, &service).await?;
+            let ingress =
+                perform_operation(self.client.clone(), Operation::Create, &ingress).await?;
+
+            let result = self.validate(&mut order, &pod, &challenges).await;
+
+            if let Err(e) = self.remove_solver(&pod, &service, &ingress).await {
+                warn!("Unable to clean up ACME solver {}: {}", name, e);
+            }
+            result?;
+        }
+
+        let mut params = request.params;
+        clear_usages(&mut params);
+        let csr = Certificate::from_params(params)?.serialize_request_der()?;
+        order.finalize(&csr).await?;
+        for _ in 0..POLL_ATTEMPTS {
+            if let Some(chain) = order.certificate().await? {
+                info!(
+                    "Certificate {} issued by {}",
+                    request.name, self.config.server
+                );
+                return Ok(IssuedCertificate {
+                    certificate: chain,
+                    ca: None,
+                });
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        Err(Error::AcmeOrderFailed(
+            "timed out waiting for the ACME certificate".into(),
+        ))
+    }
+}
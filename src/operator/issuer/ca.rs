@@ -0,0 +1,86 @@
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::Secret;
+use kube::Client;
+use rcgen::{Certificate, CertificateParams, KeyPair};
+use tracing::info;
+
+use crate::controller::Error;
+
+use super::super::perform_get;
+use super::{random_serial, CertificateRequest, IssuedCertificate, Issuer};
+
+/// The PEM certificate clients should trust for a CA Secret: `ca.crt` when the CA is an
+/// intermediate that carries its root, `tls.crt` otherwise
+pub async fn load_ca_certificate(
+    client: Client,
+    name: &str,
+    namespace: &str,
+) -> Result<String, Error> {
+    let secret: Secret = perform_get(client, name, namespace).await?;
+    let data = secret.data.unwrap_or_default();
+    let value = data
+        .get("ca.crt")
+        .or(data.get("tls.crt"))
+        .ok_or(Error::UnableToCreateObject(format!(
+            "CA Secret {}/{} is missing tls.crt",
+            namespace, name
+        )))?;
+    String::from_utf8(value.0.clone()).map_err(|e| Error::UnableToParseCertificate(e.to_string()))
+}
+
+/// Loads a CA key pair from the `tls.crt` and `tls.key` of a Secret
+pub async fn load_ca(client: Client, name: &str, namespace: &str) -> Result<Certificate, Error> {
+    let secret: Secret = perform_get(client, name, namespace).await?;
+    let data = secret.data.unwrap_or_default();
+
+    let field = |key: &str| -> Result<String, Error> {
+        let value = data.get(key).ok_or(Error::UnableToCreateObject(format!(
+            "CA Secret {}/{} is missing {}",
+            namespace, name, key
+        )))?;
+        String::from_utf8(value.0.clone())
+            .map_err(|e| Error::UnableToParseCertificate(e.to_string()))
+    };
+
+    let key_pair = KeyPair::from_pem(&field("tls.key")?)?;
+    let params = CertificateParams::from_ca_cert_pem(&field("tls.crt")?, key_pair)?;
+    Ok(Certificate::from_params(params)?)
+}
+
+/// Signs certificates locally with a CA stored in a Secret, without a CSR round trip
+pub struct CaIssuer {
+    client: Client,
+    name: String,
+    namespace: String,
+}
+
+impl CaIssuer {
+    pub fn new(client: Client, name: String, namespace: String) -> CaIssuer {
+        CaIssuer {
+            client,
+            name,
+            namespace,
+        }
+    }
+}
+
+#[async_trait]
+impl Issuer for CaIssuer {
+    async fn issue(&self, request: CertificateRequest) -> Result<IssuedCertificate, Error> {
+        let ca = load_ca(self.client.clone(), &self.name, &self.namespace).await?;
+
+        let mut params = request.params;
+        params.serial_number = Some(random_serial());
+        params.use_authority_key_identifier_extension = true;
+        let signed = Certificate::from_params(params)?.serialize_pem_with_signer(&ca)?;
+
+        info!(
+            "Certificate {} signed by CA {}/{}",
+            request.name, self.namespace, self.name
+        );
+        Ok(IssuedCertificate {
+            certificate: signed,
+            ca: Some(load_ca_certificate(self.client.clone(), &self.name, &self.namespace).await?),
+        })
+    }
+}
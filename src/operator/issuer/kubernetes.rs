@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use k8s_openapi::api::certificates::v1::{
+    CertificateSigningRequest, CertificateSigningRequestCondition, CertificateSigningRequestSpec,
+    CertificateSigningRequestStatus,
+};
+use k8s_openapi::ByteString;
+use kube::core::ResourceExt;
+use kube::Client;
+use kube::{core::ObjectMeta, Api};
+use rcgen::Certificate;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::time::sleep;
+use tracing::info;
+
+use crate::controller::Error;
+
+use super::super::signer::{clear_usages, Signer};
+use super::super::{perform_cluster_operation, Operation};
+use super::{CertificateRequest, IssuedCertificate, Issuer};
+
+/// Number of times a CSR is polled for a signed certificate before giving up
+const POLL_ATTEMPTS: u32 = 60;
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+/// Longest name Kubernetes accepts for an object
+const MAX_NAME_LENGTH: usize = 253;
+
+/// CSRs are cluster scoped, so the name is qualified with the namespace. Namespaces cannot
+/// contain dots, which keeps `<namespace>.<name>` unique; overly long names are hashed.
+fn csr_name(namespace: &str, name: &str) -> String {
+    let qualified = format!("{}.{}", namespace, name);
+    if qualified.len() <= MAX_NAME_LENGTH {
+        return qualified;
+    }
+    format!("{}.{:x}", namespace, Sha256::digest(name))
+}
+
+/// Issues certificates through the Kubernetes `CertificateSigningRequest` API
+pub struct KubernetesIssuer {
+    client: Client,
+    signer: Signer,
+}
+
+impl KubernetesIssuer {
+    pub fn new(client: Client, signer: Signer) -> KubernetesIssuer {
+        KubernetesIssuer { client, signer }
+    }
+
+    async fn create_csr(
+        &self,
+        name: &str,
+        usages: &[String],
+        duration: Duration,
+        cert: &Certificate,
+    ) -> Result<CertificateSigningRequest, Error> {
+        let raw_csr = cert.serialize_request_pem()?;
+        let expiration_seconds = i32::try_from(duration.as_secs()).map_err(|_| {
+            Error::InvalidSpec(format!("duration {:?} is too long for a CSR", duration))
+        })?;
+        let csr = CertificateSigningRequest {
+            metadata: ObjectMeta {
+                name: Some(name.into()),
+                ..Default::default()
+            },
+            spec: CertificateSigningRequestSpec {
+                expiration_seconds: Some(expiration_seconds),
+                signer_name: self.signer.name(),
+                request: ByteString(raw_csr.into_bytes().to_vec()),
+                usages: Some(usages.to_vec()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let _response =
+            perform_cluster_operation(self.client.clone(), Operation::Create, &csr).await?;
+        info!("Certificate {} created", name);
+        Ok(csr)
+    }
+
+    /// Approves the request on behalf of the signer. Subjects for signers the API server
+    /// trusts are checked against the certificate's namespace before the CSR is created.
+    async fn approve_csr(
+        &self,
+        name: &str,
+        mut request: CertificateSigningRequest,
+    ) -> Result<ByteString, Error> {
+        let csr_api: Api<CertificateSigningRequest> = Api::all(self.client.clone());
+
+        request.status = Some(CertificateSigningRequestStatus {
+            certificate: None,
+            conditions: Some(vec![CertificateSigningRequestCondition {
+                type_: String::from("Approved"),
+                message: Some(String::from("Approved by webhook-helper")),
+                reason: Some(String::from("WebHelperApproved")),
+                status: String::from("True"),
+                last_transition_time: None,
+                last_update_time: None,
+            }]),
+        });
+
+        let body: Vec<u8> = serde_json::to_vec(&request)?;
+        let url = format!(
+            "/apis/certificates.k8s.io/v1/certificatesigningrequests/{}/approval",
+            name
+        );
+        let req = http::request::Request::put(url).body(body)?;
+
+        // Deserialize JSON response as a JSON value. Alternatively, a type that
+        // implements `Deserialize` can be used.
+        let resp = self
+            .client
+            .request::<CertificateSigningRequest>(req)
+            .await?;
+
+        let name = resp.name_any();
+        for _ in 0..POLL_ATTEMPTS {
+            let status = csr_api
+                .get_approval(&name)
+                .await?
+                .status
+                .unwrap_or_default();
+            if let Some(certificate) = status.certificate {
+                info!("Certificate {} approved", name);
+                return Ok(certificate);
+            }
+            if let Some(condition) = status
+                .conditions
+                .unwrap_or_default()
+                .into_iter()
+                .find(|c| (c.type_ == "Denied" || c.type_ == "Failed") && c.status == "True")
+            {
+                return Err(Error::CertificateRequestFailed(format!(
+                    "CSR {} {}: {}",
+                    name,
+                    condition.type_.to_lowercase(),
+                    condition.message.unwrap_or_default()
+                )));
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+        Err(Error::CertificateRequestFailed(format!(
+            "CSR {} was not signed by {}",
+            name,
+            self.signer.name()
+        )))
+    }
+
+    async fn delete_csr(&self, request: &CertificateSigningRequest) -> Result<(), Error> {
+        let _response =
+            perform_cluster_operation(self.client.clone(), Operation::Delete, request).await?;
+        info!("CSR {} deleted", request.name_any());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Issuer for KubernetesIssuer {
+    async fn issue(&self, request: CertificateRequest) -> Result<IssuedCertificate, Error> {
+        let mut params = request.params;
+        clear_usages(&mut params);
+        let cert = Certificate::from_params(params)?;
+        let name = csr_name(&request.namespace, &request.name);
+        let csr = self
+            .create_csr(&name, &request.usages, request.duration, &cert)
+            .await?;
+        // The CSR is removed whether or not it was signed so the next attempt can recreate it
+        let signed = self.approve_csr(&name, csr.clone()).await;
+        self.delete_csr(&csr).await?;
+        let signed_cert = signed?;
+
+        Ok(IssuedCertificate {
+            certificate: String::from_utf8(signed_cert.0)
+                .map_err(|e| Error::UnableToParseCertificate(e.to_string()))?,
+            ca: None,
+        })
+    }
+}
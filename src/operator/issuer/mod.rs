@@ -0,0 +1,240 @@
+use async_trait::async_trait;
+use k8s_openapi::api::core::v1::ConfigMap;
+use kube::core::ResourceExt;
+use kube::{Api, Client};
+use rand::RngCore;
+use rcgen::{CertificateParams, SerialNumber};
+use std::time::Duration;
+
+use crate::controller::Error;
+use crate::crd::{
+    check_issuer_ref_namespace, is_condition_true, AcmeIssuerConfig,
+    Certificate as CertificateHelper, CertificateMode, ClusterIssuer, Issuer as IssuerResource,
+    IssuerKind, IssuerSpec, IssuerStatus, CONDITION_READY,
+};
+
+use crate::RunArgs;
+
+use super::signer::{Signer, KUBELET_SERVING, KUBE_APISERVER_CLIENT};
+
+mod acme;
+mod ca;
+mod kubernetes;
+mod self_signed;
+
+pub use acme::AcmeIssuer;
+pub use ca::{load_ca, load_ca_certificate, CaIssuer};
+pub use kubernetes::KubernetesIssuer;
+pub use self_signed::SelfSignedIssuer;
+
+/// ConfigMap the cluster CA is published in, in every namespace
+const KUBE_ROOT_CA: &str = "kube-root-ca.crt";
+
+/// Everything a backend needs to sign a certificate
+pub struct CertificateRequest {
+    /// Name for any objects created while issuing, such as the CSR
+    pub name: String,
+    /// Namespace the certificate is issued into
+    pub namespace: String,
+    /// Certificate params with the key pair and usages already set
+    pub params: CertificateParams,
+    /// Kubernetes CSR usages, e.g. `digital signature` or `server auth`, matching the
+    /// key usages in `params`
+    pub usages: Vec<String>,
+    pub duration: Duration,
+}
+
+/// The signed result of an issuer
+pub struct IssuedCertificate {
+    /// PEM encoded certificate chain
+    pub certificate: String,
+    /// PEM encoded CA certificate, when the issuer knows it
+    pub ca: Option<String>,
+}
+
+/// A backend that turns a certificate request into a signed certificate
+#[async_trait]
+pub trait Issuer {
+    async fn issue(&self, request: CertificateRequest) -> Result<IssuedCertificate, Error>;
+}
+
+/// Signing backend selected by the `issuerRef` of a `Certificate`
+pub enum ResolvedIssuer {
+    Kubernetes(Signer),
+    SelfSigned,
+    Ca { name: String, namespace: String },
+    Acme(AcmeIssuerConfig),
+}
+
+pub fn is_ready(status: &Option<IssuerStatus>) -> bool {
+    status
+        .as_ref()
+        .is_some_and(|s| is_condition_true(&s.conditions, CONDITION_READY))
+}
+
+impl ResolvedIssuer {
+    /// Looks up the issuer a `Certificate` references, failing with `IssuerNotReady` while
+    /// a referenced `Issuer` or `ClusterIssuer` is missing or not Ready
+    pub async fn resolve(
+        client: Client,
+        certificate: &CertificateHelper,
+    ) -> Result<ResolvedIssuer, Error> {
+        let spec = &certificate.spec;
+        let default_signer = match spec.mode() {
+            CertificateMode::Client => KUBE_APISERVER_CLIENT,
+            CertificateMode::Server => KUBELET_SERVING,
+        };
+        let signer = || Signer::from_name(spec.signer_name.as_deref().unwrap_or(default_signer));
+        let issuer_ref = match spec.issuer_ref.clone() {
+            Some(issuer_ref) => issuer_ref,
+            None => return Ok(ResolvedIssuer::Kubernetes(signer())),
+        };
+
+        let namespace = certificate.namespace().unwrap_or_default();
+        check_issuer_ref_namespace(&issuer_ref, &namespace).map_err(Error::InvalidSpec)?;
+        let name = || {
+            issuer_ref.name.clone().ok_or(Error::InvalidSpec(format!(
+                "issuerRef.name is required for {:?} issuers",
+                issuer_ref.kind
+            )))
+        };
+
+        match issuer_ref.kind {
+            IssuerKind::KubernetesCsr => Ok(ResolvedIssuer::Kubernetes(signer())),
+            IssuerKind::SelfSigned => Ok(ResolvedIssuer::SelfSigned),
+            IssuerKind::Ca => Ok(ResolvedIssuer::Ca {
+                name: name()?,
+                namespace,
+            }),
+            IssuerKind::Issuer => {
+                let name = name()?;
+                let api: Api<IssuerResource> = Api::namespaced(client, &namespace);
+                let issuer = api
+                    .get_opt(&name)
+                    .await?
+                    .ok_or(Error::IssuerNotReady(format!(
+                        "Issuer {}/{} not found",
+                        namespace, name
+                    )))?;
+                if !is_ready(&issuer.status) {
+                    return Err(Error::IssuerNotReady(format!(
+                        "Issuer {}/{} is not Ready",
+                        namespace, name
+                    )));
+                }
+                issuer.validate().map_err(Error::InvalidSpec)?;
+                ResolvedIssuer::from_spec(&issuer.spec, Some(&namespace))
+            }
+            IssuerKind::ClusterIssuer => {
+                let name = name()?;
+                let api: Api<ClusterIssuer> = Api::all(client);
+                let issuer = api
+                    .get_opt(&name)
+                    .await?
+                    .ok_or(Error::IssuerNotReady(format!(
+                        "ClusterIssuer {} not found",
+                        name
+                    )))?;
+                if !is_ready(&issuer.status) {
+                    return Err(Error::IssuerNotReady(format!(
+                        "ClusterIssuer {} is not Ready",
+                        name
+                    )));
+                }
+                ResolvedIssuer::from_spec(&issuer.spec.issuer, None)
+            }
+        }
+    }
+
+    fn from_spec(spec: &IssuerSpec, namespace: Option<&str>) -> Result<ResolvedIssuer, Error> {
+        if let Some(kubernetes) = &spec.kubernetes {
+            return Ok(ResolvedIssuer::Kubernetes(Signer::from_name(
+                &kubernetes.signer_name,
+            )));
+        }
+        if let Some(ca) = &spec.ca {
+            let namespace = ca
+                .namespace
+                .as_deref()
+                .or(namespace)
+                .ok_or(Error::InvalidSpec("ca.namespace is required".into()))?;
+            return Ok(ResolvedIssuer::Ca {
+                name: ca.secret_name.clone(),
+                namespace: namespace.into(),
+            });
+        }
+        if spec.self_signed.is_some() {
+            return Ok(ResolvedIssuer::SelfSigned);
+        }
+        if let Some(acme) = &spec.acme {
+            return Ok(ResolvedIssuer::Acme(acme.clone()));
+        }
+        Err(Error::InvalidSpec(
+            "issuer has no backend configured".into(),
+        ))
+    }
+
+    /// Rules the certificate has to follow to be accepted by this issuer
+    pub fn signer(&self) -> Signer {
+        match self {
+            ResolvedIssuer::Kubernetes(signer) => signer.clone(),
+            _ => Signer::Local,
+        }
+    }
+
+    /// PEM of the CA that signs certificates from this issuer, when it can be looked up.
+    /// The built-in Kubernetes signers use the cluster CA published in `kube-root-ca.crt`.
+    pub async fn ca_certificate(
+        &self,
+        client: Client,
+        namespace: &str,
+        args: &RunArgs,
+    ) -> Result<Option<String>, Error> {
+        match self {
+            ResolvedIssuer::Kubernetes(Signer::HelperCa) => Ok(Some(
+                load_ca_certificate(client, &args.ca_secret_name, &args.ca_secret_namespace)
+                    .await?,
+            )),
+            ResolvedIssuer::Kubernetes(Signer::Custom(_)) => Ok(None),
+            ResolvedIssuer::Kubernetes(_) => {
+                let api: Api<ConfigMap> = Api::namespaced(client, namespace);
+                Ok(api
+                    .get_opt(KUBE_ROOT_CA)
+                    .await?
+                    .and_then(|c| c.data)
+                    .and_then(|d| d.get("ca.crt").cloned()))
+            }
+            ResolvedIssuer::Ca { name, namespace } => {
+                Ok(Some(load_ca_certificate(client, name, namespace).await?))
+            }
+            ResolvedIssuer::SelfSigned | ResolvedIssuer::Acme(_) => Ok(None),
+        }
+    }
+
+    /// Whether certificates are issued by a public CA, which cannot validate in-cluster names
+    pub fn is_public(&self) -> bool {
+        matches!(self, ResolvedIssuer::Acme(_))
+    }
+
+    pub fn issuer(&self, client: Client) -> Result<Box<dyn Issuer + Send + Sync>, Error> {
+        let issuer: Box<dyn Issuer + Send + Sync> = match self {
+            ResolvedIssuer::Kubernetes(signer) => {
+                Box::new(KubernetesIssuer::new(client, signer.clone()))
+            }
+            ResolvedIssuer::SelfSigned => Box::new(SelfSignedIssuer),
+            ResolvedIssuer::Ca { name, namespace } => {
+                Box::new(CaIssuer::new(client, name.clone(), namespace.clone()))
+            }
+            ResolvedIssuer::Acme(acme) => Box::new(AcmeIssuer::new(client, acme.clone())),
+        };
+        Ok(issuer)
+    }
+}
+
+/// Random positive serial number for certificates signed in-process
+pub fn random_serial() -> SerialNumber {
+    let mut serial = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut serial);
+    serial[0] &= 0x7f;
+    SerialNumber::from_slice(&serial)
+}
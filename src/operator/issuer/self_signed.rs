@@ -0,0 +1,25 @@
+use async_trait::async_trait;
+use rcgen::Certificate;
+use tracing::info;
+
+use crate::controller::Error;
+
+use super::{random_serial, CertificateRequest, IssuedCertificate, Issuer};
+
+/// Signs every certificate with its own key, for clusters without a working signer
+pub struct SelfSignedIssuer;
+
+#[async_trait]
+impl Issuer for SelfSignedIssuer {
+    async fn issue(&self, request: CertificateRequest) -> Result<IssuedCertificate, Error> {
+        let mut params = request.params;
+        params.serial_number = Some(random_serial());
+        let signed = Certificate::from_params(params)?.serialize_pem()?;
+
+        info!("Certificate {} self-signed", request.name);
+        Ok(IssuedCertificate {
+            ca: Some(signed.clone()),
+            certificate: signed,
+        })
+    }
+}
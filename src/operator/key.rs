@@ -0,0 +1,51 @@
+use rcgen::{
+    KeyPair, PKCS_ECDSA_P256_SHA256, PKCS_ECDSA_P384_SHA384, PKCS_ED25519, PKCS_RSA_SHA256,
+};
+use rsa::pkcs8::EncodePrivateKey;
+use rsa::RsaPrivateKey;
+
+use crate::controller::Error;
+use crate::crd::{KeyAlgorithm, PrivateKey};
+
+/// Generates a key pair as described by `privateKey` on the blocking thread pool, as RSA
+/// keys can take seconds to generate and would otherwise stall the runtime
+pub async fn generate_key_pair(private_key: &PrivateKey) -> Result<KeyPair, Error> {
+    let private_key = private_key.clone();
+    tokio::task::spawn_blocking(move || generate(&private_key))
+        .await
+        .map_err(|e| Error::UnableToGenerateKey(e.to_string()))?
+}
+
+/// ring cannot generate RSA keys, so those are created with the `rsa` crate and handed to
+/// rcgen as PKCS#8
+fn generate(private_key: &PrivateKey) -> Result<KeyPair, Error> {
+    match (private_key.algorithm(), private_key.size()) {
+        (KeyAlgorithm::Rsa, Some(bits)) => {
+            let key = RsaPrivateKey::new(&mut rand::thread_rng(), bits as usize)
+                .map_err(|e| Error::UnableToGenerateKey(e.to_string()))?;
+            let der = key
+                .to_pkcs8_der()
+                .map_err(|e| Error::UnableToGenerateKey(e.to_string()))?;
+            Ok(KeyPair::from_der_and_sign_algo(
+                der.as_bytes(),
+                &PKCS_RSA_SHA256,
+            )?)
+        }
+        (KeyAlgorithm::Ecdsa, Some(384)) => Ok(KeyPair::generate(&PKCS_ECDSA_P384_SHA384)?),
+        (KeyAlgorithm::Ecdsa, _) => Ok(KeyPair::generate(&PKCS_ECDSA_P256_SHA256)?),
+        (KeyAlgorithm::Ed25519, _) => Ok(KeyPair::generate(&PKCS_ED25519)?),
+        (algorithm, size) => Err(Error::UnableToGenerateKey(format!(
+            "unsupported key {:?} {:?}",
+            algorithm, size
+        ))),
+    }
+}
+
+/// Short description recorded in the status, e.g. `RSA-2048` or `ECDSA-P256`
+pub fn describe(private_key: &PrivateKey) -> String {
+    match (private_key.algorithm(), private_key.size()) {
+        (KeyAlgorithm::Rsa, Some(bits)) => format!("RSA-{}", bits),
+        (KeyAlgorithm::Ecdsa, Some(bits)) => format!("ECDSA-P{}", bits),
+        (algorithm, _) => format!("{:?}", algorithm),
+    }
+}
@@ -0,0 +1,289 @@
+use p12_keystore::{Certificate, KeyStore, KeyStoreEntry, PrivateKeyChain};
+use rand::RngCore;
+use sha1::{Digest, Sha1};
+use std::time::{SystemTime, UNIX_EPOCH};
+use x509_parser::pem::Pem;
+
+use crate::controller::Error;
+
+/// Alias of the certificate and key in a keystore
+const KEY_ALIAS: &str = "certificate";
+/// Alias of the CA in a truststore
+const CA_ALIAS: &str = "ca";
+
+/// Sun's proprietary key protection algorithm used by JKS, 1.3.6.1.4.1.42.2.17.1.1
+const JKS_KEY_PROTECTOR_OID: [u8; 10] =
+    [0x2b, 0x06, 0x01, 0x04, 0x01, 0x2a, 0x02, 0x11, 0x01, 0x01];
+const JKS_MAGIC: u32 = 0xfeed_feed;
+const JKS_VERSION: u32 = 2;
+const JKS_PRIVATE_KEY_TAG: u32 = 1;
+const JKS_TRUSTED_CERT_TAG: u32 = 2;
+
+/// DER certificates of a PEM bundle, leaf first
+pub fn pem_certificates(pem: &str) -> Result<Vec<Vec<u8>>, Error> {
+    Pem::iter_from_buffer(pem.as_bytes())
+        .map(|p| {
+            p.map(|p| p.contents)
+                .map_err(|e| Error::UnableToCreateKeystore(e.to_string()))
+        })
+        .collect()
+}
+
+fn p12_certificate(der: &[u8]) -> Result<Certificate, Error> {
+    Certificate::from_der(der).map_err(|e| Error::UnableToCreateKeystore(e.to_string()))
+}
+
+/// PKCS#12 keystore holding the PKCS#8 `key` and its certificate `chain`
+pub fn pkcs12_keystore(key: &[u8], chain: &[Vec<u8>], password: &str) -> Result<Vec<u8>, Error> {
+    let certificates = chain
+        .iter()
+        .map(|der| p12_certificate(der))
+        .collect::<Result<Vec<_>, _>>()?;
+    let local_key_id = Sha1::digest(chain.first().map(|c| c.as_slice()).unwrap_or_default());
+
+    let mut keystore = KeyStore::new();
+    keystore.add_entry(
+        KEY_ALIAS,
+        KeyStoreEntry::PrivateKeyChain(PrivateKeyChain::new(key, local_key_id, certificates)),
+    );
+    keystore
+        .writer(password)
+        .write()
+        .map_err(|e| Error::UnableToCreateKeystore(e.to_string()))
+}
+
+/// PKCS#12 truststore holding the CA as a trusted certificate
+pub fn pkcs12_truststore(ca: &[u8], password: &str) -> Result<Vec<u8>, Error> {
+    let mut keystore = KeyStore::new();
+    keystore.add_entry(CA_ALIAS, KeyStoreEntry::Certificate(p12_certificate(ca)?));
+    keystore
+        .writer(password)
+        .write()
+        .map_err(|e| Error::UnableToCreateKeystore(e.to_string()))
+}
+
+/// JKS keystore holding the PKCS#8 `key` and its certificate `chain`
+pub fn jks_keystore(key: &[u8], chain: &[Vec<u8>], password: &str) -> Vec<u8> {
+    let mut writer = JksWriter::new(1);
+    writer.u32(JKS_PRIVATE_KEY_TAG);
+    writer.entry_header(KEY_ALIAS);
+    writer.bytes(&protect_key(key, password));
+    writer.u32(chain.len() as u32);
+    for der in chain {
+        writer.certificate(der);
+    }
+    writer.finish(password)
+}
+
+/// JKS truststore holding the CA as a trusted certificate
+pub fn jks_truststore(ca: &[u8], password: &str) -> Vec<u8> {
+    let mut writer = JksWriter::new(1);
+    writer.u32(JKS_TRUSTED_CERT_TAG);
+    writer.entry_header(CA_ALIAS);
+    writer.certificate(ca);
+    writer.finish(password)
+}
+
+/// Java chars are UTF-16, JKS hashes passwords as big endian UTF-16
+fn password_bytes(password: &str) -> Vec<u8> {
+    password
+        .encode_utf16()
+        .flat_map(|c| c.to_be_bytes())
+        .collect()
+}
+
+fn der_length(length: usize) -> Vec<u8> {
+    if length < 0x80 {
+        return vec![length as u8];
+    }
+    let bytes: Vec<u8> = length
+        .to_be_bytes()
+        .into_iter()
+        .skip_while(|b| *b == 0)
+        .collect();
+    let mut encoded = vec![0x80 | bytes.len() as u8];
+    encoded.extend(bytes);
+    encoded
+}
+
+fn der(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut encoded = vec![tag];
+    encoded.extend(der_length(contents.len()));
+    encoded.extend_from_slice(contents);
+    encoded
+}
+
+/// Encrypts a PKCS#8 key the way `sun.security.provider.KeyProtector` does: XOR with a SHA-1
+/// keystream seeded by a random salt, followed by a SHA-1 integrity check, wrapped in an
+/// `EncryptedPrivateKeyInfo`
+fn protect_key(key: &[u8], password: &str) -> Vec<u8> {
+    let password = password_bytes(password);
+    let mut salt = [0u8; 20];
+    rand::thread_rng().fill_bytes(&mut salt);
+
+    let mut keystream = Vec::with_capacity(key.len() + 20);
+    let mut digest = salt.to_vec();
+    while keystream.len() < key.len() {
+        digest = Sha1::new()
+            .chain_update(&password)
+            .chain_update(&digest)
+            .finalize()
+            .to_vec();
+        keystream.extend_from_slice(&digest);
+    }
+
+    let mut protected = salt.to_vec();
+    protected.extend(key.iter().zip(keystream).map(|(k, s)| k ^ s));
+    protected.extend(
+        Sha1::new()
+            .chain_update(&password)
+            .chain_update(key)
+            .finalize(),
+    );
+
+    let mut algorithm = der(0x06, &JKS_KEY_PROTECTOR_OID);
+    algorithm.extend(der(0x05, &[]));
+    let mut info = der(0x30, &algorithm);
+    info.extend(der(0x04, &protected));
+    der(0x30, &info)
+}
+
+/// Writes the big endian JKS layout and its trailing keyed SHA-1 digest
+struct JksWriter {
+    data: Vec<u8>,
+}
+
+impl JksWriter {
+    fn new(entries: u32) -> JksWriter {
+        let mut writer = JksWriter { data: Vec::new() };
+        writer.u32(JKS_MAGIC);
+        writer.u32(JKS_VERSION);
+        writer.u32(entries);
+        writer
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_be_bytes());
+    }
+
+    /// Java modified UTF-8, identical to UTF-8 for the ASCII used here
+    fn utf(&mut self, value: &str) {
+        self.data.extend((value.len() as u16).to_be_bytes());
+        self.data.extend(value.as_bytes());
+    }
+
+    fn bytes(&mut self, value: &[u8]) {
+        self.u32(value.len() as u32);
+        self.data.extend(value);
+    }
+
+    fn entry_header(&mut self, alias: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        self.utf(alias);
+        self.data.extend(now.to_be_bytes());
+    }
+
+    fn certificate(&mut self, der: &[u8]) {
+        self.utf("X.509");
+        self.bytes(der);
+    }
+
+    fn finish(mut self, password: &str) -> Vec<u8> {
+        let digest = Sha1::new()
+            .chain_update(password_bytes(password))
+            .chain_update(b"Mighty Aphrodite")
+            .chain_update(&self.data)
+            .finalize();
+        self.data.extend(digest);
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "changeit";
+
+    /// Reads JKS fields back in the order `JksWriter` wrote them
+    struct Reader<'a> {
+        data: &'a [u8],
+    }
+
+    impl<'a> Reader<'a> {
+        fn take(&mut self, length: usize) -> &'a [u8] {
+            let (value, rest) = self.data.split_at(length);
+            self.data = rest;
+            value
+        }
+
+        fn u32(&mut self) -> u32 {
+            u32::from_be_bytes(self.take(4).try_into().unwrap())
+        }
+
+        fn utf(&mut self) -> &'a [u8] {
+            let length = u16::from_be_bytes(self.take(2).try_into().unwrap());
+            self.take(length as usize)
+        }
+
+        fn bytes(&mut self) -> &'a [u8] {
+            let length = self.u32();
+            self.take(length as usize)
+        }
+    }
+
+    fn sha1(parts: &[&[u8]]) -> Vec<u8> {
+        parts
+            .iter()
+            .fold(Sha1::new(), |hasher, part| hasher.chain_update(part))
+            .finalize()
+            .to_vec()
+    }
+
+    #[test]
+    fn jks_keystore_digests_match_key_protector() {
+        let cert = rcgen::generate_simple_self_signed(vec!["example.com".into()]).unwrap();
+        let key = cert.serialize_private_key_der();
+        let chain = vec![cert.serialize_der().unwrap()];
+        let keystore = jks_keystore(&key, &chain, PASSWORD);
+        let password = password_bytes(PASSWORD);
+
+        let (body, digest) = keystore.split_at(keystore.len() - 20);
+        assert_eq!(digest, sha1(&[&password, b"Mighty Aphrodite", body]));
+
+        let mut reader = Reader { data: body };
+        assert_eq!(reader.u32(), JKS_MAGIC);
+        assert_eq!(reader.u32(), JKS_VERSION);
+        assert_eq!(reader.u32(), 1);
+        assert_eq!(reader.u32(), JKS_PRIVATE_KEY_TAG);
+        assert_eq!(reader.utf(), KEY_ALIAS.as_bytes());
+        reader.take(8);
+
+        // The protected key closes the EncryptedPrivateKeyInfo: salt, encrypted key and
+        // the integrity digest
+        let info = reader.bytes();
+        assert!(info
+            .windows(JKS_KEY_PROTECTOR_OID.len())
+            .any(|w| w == JKS_KEY_PROTECTOR_OID));
+        let protected = &info[info.len() - key.len() - 40..];
+        let (salt, rest) = protected.split_at(20);
+        let (encrypted, integrity) = rest.split_at(key.len());
+
+        let mut digest = salt.to_vec();
+        let mut recovered = Vec::new();
+        for chunk in encrypted.chunks(20) {
+            digest = sha1(&[&password, &digest]);
+            recovered.extend(chunk.iter().zip(&digest).map(|(c, s)| c ^ s));
+        }
+        assert_eq!(recovered, key);
+        assert_eq!(integrity, sha1(&[&password, &recovered]));
+
+        assert_eq!(reader.u32(), 1);
+        assert_eq!(reader.utf(), b"X.509");
+        assert_eq!(reader.bytes(), chain[0].as_slice());
+        assert!(reader.data.is_empty());
+    }
+}
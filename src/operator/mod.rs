@@ -1,7 +1,16 @@
 mod certificate;
+mod issuer;
+mod key;
+mod keystore;
+mod signer;
 mod utils;
+mod x509;
 
-pub use certificate::CertificateStage;
+pub use certificate::{CertificateStage, CERTIFICATE_LABEL};
+
+pub use issuer::{load_ca, random_serial};
+
+pub use signer::{apply_usages, HELPER_CA};
 
 pub use utils::{
     determine_stage, perform_cluster_operation, perform_get, perform_operation, update_status,
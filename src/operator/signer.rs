@@ -0,0 +1,169 @@
+use rcgen::{
+    CertificateParams, DistinguishedName, DnType, ExtendedKeyUsagePurpose, KeyUsagePurpose,
+};
+
+use crate::crd::Subject;
+
+/// X.520 serialNumber, which rcgen has no named `DnType` for
+const OID_SERIAL_NUMBER: [u64; 4] = [2, 5, 4, 5];
+
+pub const KUBELET_SERVING: &str = "kubernetes.io/kubelet-serving";
+pub const KUBE_APISERVER_CLIENT: &str = "kubernetes.io/kube-apiserver-client";
+/// Signer implemented by certificate-helper itself, see `ca_signer`
+pub const HELPER_CA: &str = "certificate-helper.io/ca";
+
+/// The signer a CSR is addressed to, along with the rules it enforces on the request
+#[derive(Clone)]
+pub enum Signer {
+    KubeletServing,
+    KubeApiserverClient,
+    HelperCa,
+    Custom(String),
+    /// Signed in-process or by an external CA rather than a CSR signer, no signer rules apply
+    Local,
+}
+
+impl Signer {
+    pub fn from_name(name: &str) -> Signer {
+        match name {
+            KUBELET_SERVING => Signer::KubeletServing,
+            KUBE_APISERVER_CLIENT => Signer::KubeApiserverClient,
+            HELPER_CA => Signer::HelperCa,
+            _ => Signer::Custom(name.into()),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Signer::KubeletServing => KUBELET_SERVING.into(),
+            Signer::KubeApiserverClient => KUBE_APISERVER_CLIENT.into(),
+            Signer::HelperCa => HELPER_CA.into(),
+            Signer::Custom(name) => name.clone(),
+            Signer::Local => String::new(),
+        }
+    }
+
+    /// Whether the API server accepts certificates from this signer as client credentials
+    pub fn is_apiserver_trusted(&self) -> bool {
+        match self {
+            Signer::KubeApiserverClient => true,
+            Signer::Custom(name) => name.starts_with(KUBE_APISERVER_CLIENT),
+            _ => false,
+        }
+    }
+
+    /// kubelet-serving only signs requests that claim to come from a node, every other
+    /// signer takes the requested subject, with the service as the default common name.
+    /// Subjects for signers the API server trusts must stay within `namespace`.
+    pub fn distinguished_name(
+        &self,
+        namespace: &str,
+        service: Option<&str>,
+        subject: Option<&Subject>,
+    ) -> Result<DistinguishedName, String> {
+        let mut dn = DistinguishedName::new();
+        if let Signer::KubeletServing = self {
+            dn.push(DnType::OrganizationName, "system:nodes");
+            dn.push(
+                DnType::CommonName,
+                format!("system:node:{}", service.unwrap_or_default()),
+            );
+            return Ok(dn);
+        }
+
+        let mut subject = subject.cloned().unwrap_or_default();
+        subject.common_name = subject.common_name.or(service.map(String::from));
+        if self.is_apiserver_trusted() {
+            subject.check_apiserver_identity(namespace)?;
+        }
+        let first = |values: Option<Vec<String>>| values.and_then(|v| v.into_iter().next());
+        let entries = [
+            (DnType::CountryName, first(subject.countries)),
+            (DnType::StateOrProvinceName, first(subject.provinces)),
+            (DnType::LocalityName, first(subject.localities)),
+            (DnType::OrganizationName, first(subject.organizations)),
+            (
+                DnType::OrganizationalUnitName,
+                first(subject.organizational_units),
+            ),
+            (DnType::CommonName, subject.common_name),
+            (
+                DnType::CustomDnType(OID_SERIAL_NUMBER.to_vec()),
+                subject.serial_number,
+            ),
+        ];
+        for (dn_type, value) in entries {
+            if let Some(value) = value {
+                dn.push(dn_type, value);
+            }
+        }
+        Ok(dn)
+    }
+
+    /// The CSR usages the signer expects for a certificate it issues
+    pub fn usages(&self) -> Vec<String> {
+        match self {
+            Signer::KubeApiserverClient => client_usages(),
+            _ => vec![
+                "digital signature".into(),
+                "key encipherment".into(),
+                "server auth".into(),
+            ],
+        }
+    }
+}
+
+/// Usages of a client certificate used for mTLS
+pub fn client_usages() -> Vec<String> {
+    vec![
+        "digital signature".into(),
+        "key encipherment".into(),
+        "client auth".into(),
+    ]
+}
+
+fn key_usage(usage: &str) -> Option<KeyUsagePurpose> {
+    let purpose = match usage {
+        "signing" | "digital signature" => KeyUsagePurpose::DigitalSignature,
+        "content commitment" => KeyUsagePurpose::ContentCommitment,
+        "key encipherment" => KeyUsagePurpose::KeyEncipherment,
+        "data encipherment" => KeyUsagePurpose::DataEncipherment,
+        "key agreement" => KeyUsagePurpose::KeyAgreement,
+        "cert sign" => KeyUsagePurpose::KeyCertSign,
+        "crl sign" => KeyUsagePurpose::CrlSign,
+        "encipher only" => KeyUsagePurpose::EncipherOnly,
+        "decipher only" => KeyUsagePurpose::DecipherOnly,
+        _ => return None,
+    };
+    Some(purpose)
+}
+
+fn extended_key_usage(usage: &str) -> Option<ExtendedKeyUsagePurpose> {
+    let purpose = match usage {
+        "any" => ExtendedKeyUsagePurpose::Any,
+        "server auth" => ExtendedKeyUsagePurpose::ServerAuth,
+        "client auth" => ExtendedKeyUsagePurpose::ClientAuth,
+        "code signing" => ExtendedKeyUsagePurpose::CodeSigning,
+        "email protection" | "s/mime" => ExtendedKeyUsagePurpose::EmailProtection,
+        "timestamping" => ExtendedKeyUsagePurpose::TimeStamping,
+        "ocsp signing" => ExtendedKeyUsagePurpose::OcspSigning,
+        _ => return None,
+    };
+    Some(purpose)
+}
+
+/// Sets the key usages and extended key usages matching a list of CSR usages
+pub fn apply_usages(params: &mut CertificateParams, usages: &[String]) {
+    params.key_usages = usages.iter().filter_map(|u| key_usage(u)).collect();
+    params.extended_key_usages = usages
+        .iter()
+        .filter_map(|u| extended_key_usage(u))
+        .collect();
+}
+
+/// rcgen refuses to write key usages into a CSR, so params are stripped of them before a
+/// request is serialized and the usages travel in `spec.usages` instead
+pub fn clear_usages(params: &mut CertificateParams) {
+    params.key_usages.clear();
+    params.extended_key_usages.clear();
+}
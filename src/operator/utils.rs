@@ -3,22 +3,17 @@ use kube::core::ResourceExt;
 use kube::Api;
 use kube::Client;
 
-use serde_json::Value;
-
-use kube::api::Patch;
-use kube::api::PatchParams;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 use std::fmt::Debug;
 
 use crate::controller::Error;
-use crate::crd::{Certificate, CertificateCondition, CertificateStatus, Stage};
-use chrono::offset::Utc;
-use chrono::DateTime;
+use crate::crd::{
+    is_condition_true, set_condition, Certificate, CertificateCondition, CertificateStatus, Stage,
+    CONDITION_FAILED, CONDITION_ISSUING, CONDITION_READY,
+};
 use k8s_openapi::{ClusterResourceScope, NamespaceResourceScope};
 use kube::Resource;
-use std::time::SystemTime;
 use tracing::info;
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -27,33 +22,9 @@ pub enum Operation {
     Create,
     Update,
     Delete,
-    ApplyOwner(String),
     Unknown(String),
 }
 
-async fn apply_owner<T>(api: Api<T>, owner: String, value: &T) -> Result<(), Error>
-where
-    T: Clone + DeserializeOwned + Debug + Serialize + ResourceExt,
-{
-    let owner_ref: Value = json!({
-        "metadata": {
-            "ownerReferences": [{
-                "apiVersion": "v1",
-                "kind": "WebhookHelper",
-                "name": "webhook-helper.io",
-                "uid": owner,
-                "blockOwnerDeletion": false
-            }]
-        }
-    });
-
-    let patch: Patch<&Value> = Patch::Merge(&owner_ref);
-    api.patch(&value.name_any(), &PatchParams::default(), &patch)
-        .await?;
-
-    Ok(())
-}
-
 pub async fn perform_operation<T>(
     client: Client,
     operation: Operation,
@@ -86,10 +57,6 @@ where
             Ok(result)
         }
         Operation::Unknown(op) => Err(Error::UnknownOperation(op)),
-        Operation::ApplyOwner(owner) => {
-            apply_owner(api, owner, value).await?;
-            Ok(value.clone())
-        }
     }
 }
 
@@ -136,10 +103,6 @@ where
             Ok(result)
         }
         Operation::Unknown(op) => Err(Error::UnknownOperation(op)),
-        Operation::ApplyOwner(owner) => {
-            apply_owner(api, owner, value).await?;
-            Ok(value.clone())
-        }
     }
 }
 
@@ -150,7 +113,8 @@ pub async fn update_status(
 ) -> Result<Certificate, Error> {
     info!("Updating Status");
     let pp = PostParams::default();
-    let api: Api<Certificate> = Api::all(client.clone());
+    let api: Api<Certificate> =
+        Api::namespaced(client.clone(), &resource.namespace().unwrap_or_default());
 
     let mut result = api.get_status(&resource.name_any()).await?;
 
@@ -159,30 +123,50 @@ pub async fn update_status(
         None => CertificateStatus::default(),
     };
 
-    let datetime: DateTime<Utc> = SystemTime::now().into();
-    let mut condition_entry = CertificateCondition {
-        type__: format!("{}", stage),
-        message: stage.message(),
-        status: "True".into(),
-        last_transition_time: format!("{}", datetime.format("%d/%m/%Y %T")),
+    let generation = resource.metadata.generation;
+    let message = stage.message();
+    let condition = |type_: &str, value: bool, reason: &str| {
+        CertificateCondition::new(type_, value, reason, message.clone(), generation)
     };
 
-    match stage {
-        Stage::CreationFailed(_) => {
-            condition_entry.status = "False".into();
-        }
+    let updates = match &stage {
+        Stage::Creating => vec![condition(CONDITION_ISSUING, true, "Issuing")],
         Stage::CertificateCreated(c) => {
-            status.certificate = Some(c);
+            status.certificate = Some(c.clone());
+            vec![
+                condition(CONDITION_READY, true, "Issued"),
+                condition(CONDITION_ISSUING, false, "Issued"),
+                condition(CONDITION_FAILED, false, "Issued"),
+            ]
+        }
+        Stage::CreationFailed(_) => {
+            let mut updates = vec![
+                condition(CONDITION_FAILED, true, "IssuanceFailed"),
+                condition(CONDITION_ISSUING, false, "IssuanceFailed"),
+            ];
+            // A certificate issued earlier stays usable until it expires
+            if !is_condition_true(&status.conditions, CONDITION_READY) {
+                updates.push(condition(CONDITION_READY, false, "IssuanceFailed"));
+            }
+            updates
         }
-        _ => {}
+        Stage::Deleting => vec![condition(CONDITION_READY, false, "Deleting")],
     };
 
-    if let Some(mut v) = status.conditions.clone() {
-        v.push(condition_entry);
-        status.conditions = Some(v);
-    } else {
-        status.conditions = Some(vec![condition_entry]);
-    };
+    // Drop the per-stage conditions written by earlier versions
+    let mut conditions: Vec<CertificateCondition> = status
+        .conditions
+        .take()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|c| {
+            [CONDITION_READY, CONDITION_ISSUING, CONDITION_FAILED].contains(&c.type__.as_str())
+        })
+        .collect();
+    for update in updates {
+        set_condition(&mut conditions, update);
+    }
+    status.conditions = Some(conditions);
 
     result.status = Some(status);
 
@@ -193,31 +177,27 @@ pub async fn update_status(
     Ok(updated_status)
 }
 
+/// Works out the stage from the conditions: a failed attempt takes precedence, otherwise
+/// any recorded Secret means a certificate was issued
 pub async fn determine_stage(client: Client, value: Certificate) -> Result<Stage, Error> {
-    let api: Api<Certificate> = Api::all(client.clone());
-    let result = api.get_status(&value.name_any()).await?;
-    match result.status {
-        Some(status) => {
-            if let Some(conditions) = status.conditions {
-                if let Some(last) = conditions.last() {
-                    let result = match last.type__.as_str() {
-                        "CertificateCreated" => Ok(Stage::CertificateCreated(
-                            status.certificate.unwrap_or("<unknown>".into()),
-                        )),
-                        "CreationFailed" => Ok(Stage::CreationFailed(last.message.clone())),
-                        _ => Err(Error::UnknownOperation(format!(
-                            "Unable to determine condition type: {}",
-                            last.type__
-                        ))),
-                    };
-                    result
-                } else {
-                    Ok(Stage::Creating)
-                }
-            } else {
-                Ok(Stage::Creating)
-            }
-        }
+    let api: Api<Certificate> =
+        Api::namespaced(client.clone(), &value.namespace().unwrap_or_default());
+    let status = api
+        .get_status(&value.name_any())
+        .await?
+        .status
+        .unwrap_or_default();
+
+    let failed = status
+        .conditions
+        .iter()
+        .flatten()
+        .find(|c| c.type__ == CONDITION_FAILED && c.status == "True");
+    if let Some(failed) = failed {
+        return Ok(Stage::CreationFailed(failed.message.clone()));
+    }
+    match status.certificate {
+        Some(secret) => Ok(Stage::CertificateCreated(secret)),
         None => Ok(Stage::Creating),
     }
 }
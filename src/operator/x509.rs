@@ -0,0 +1,70 @@
+use chrono::offset::Utc;
+use chrono::DateTime;
+use sha2::{Digest, Sha256};
+use std::time::Duration;
+use x509_parser::pem::parse_x509_pem;
+
+use crate::controller::Error;
+
+/// Details read back from an issued PEM certificate
+pub struct CertificateInfo {
+    pub not_before: DateTime<Utc>,
+    pub not_after: DateTime<Utc>,
+    /// Colon separated hex, e.g. `4F:0A:...`
+    pub serial_number: String,
+    /// SHA-256 of the DER encoding, formatted like the serial number
+    pub fingerprint_sha256: String,
+    /// Distinguished name of the issuer, e.g. `CN=kubernetes`
+    pub issuer: String,
+    /// Raw subject public key, comparable with `KeyPair::public_key_raw`
+    pub public_key: Vec<u8>,
+}
+
+impl CertificateInfo {
+    /// The point at which the certificate should be renewed, either `renew_before` ahead of
+    /// expiry or once `fraction` of the lifetime (e.g. `2.0 / 3.0`) has passed. Signers may
+    /// issue shorter lifetimes than requested, so `fraction` is also used when `renew_before`
+    /// covers the whole lifetime.
+    pub fn renewal_time(&self, fraction: f64, renew_before: Option<Duration>) -> DateTime<Utc> {
+        let lifetime = (self.not_after - self.not_before).num_seconds();
+        if let Some(renew_before) = renew_before.map(|d| d.as_secs() as i64) {
+            if renew_before < lifetime {
+                return self.not_after - chrono::Duration::seconds(renew_before);
+            }
+        }
+        self.not_before + chrono::Duration::seconds((lifetime as f64 * fraction) as i64)
+    }
+}
+
+fn timestamp(seconds: i64) -> Result<DateTime<Utc>, Error> {
+    DateTime::from_timestamp(seconds, 0).ok_or(Error::UnableToParseCertificate(format!(
+        "invalid timestamp {}",
+        seconds
+    )))
+}
+
+fn colon_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// Parses the first certificate of a PEM bundle
+pub fn parse_certificate(pem: &[u8]) -> Result<CertificateInfo, Error> {
+    let (_, pem) =
+        parse_x509_pem(pem).map_err(|e| Error::UnableToParseCertificate(e.to_string()))?;
+    let cert = pem
+        .parse_x509()
+        .map_err(|e| Error::UnableToParseCertificate(e.to_string()))?;
+
+    Ok(CertificateInfo {
+        not_before: timestamp(cert.validity().not_before.timestamp())?,
+        not_after: timestamp(cert.validity().not_after.timestamp())?,
+        serial_number: colon_hex(cert.raw_serial()),
+        fingerprint_sha256: colon_hex(&Sha256::digest(&pem.contents)),
+        issuer: cert.issuer().to_string(),
+        public_key: cert.public_key().subject_public_key.data.to_vec(),
+    })
+}